@@ -0,0 +1,130 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput, black_box};
+use core::sync::atomic::*;
+
+use queueue::queue::nonblocking::StaticSpinQueue;
+use queueue::queue::nonblocking::Queue;
+use queueue::queue::buffered::{BufferedProducer, BufferedConsumer};
+
+const BATCH: usize = 32;
+
+static STOP_SIG: AtomicBool = AtomicBool::new(false);
+
+fn per_item_bench(b: &mut Criterion) {
+    let queue: &'static mut StaticSpinQueue::<usize, 128> = Box::leak(Box::new(Default::default()));
+
+    STOP_SIG.store(false, Ordering::Release);
+
+    let consumer = queue.consumer().unwrap();
+    let handle = std::thread::spawn(move || {
+        while !STOP_SIG.load(Ordering::Acquire) {
+            black_box(consumer.pop());
+        }
+    });
+
+    let producer = queue.producer().unwrap();
+    b.bench_function("Per-item enqueue 1000", |b| b.iter(|| {
+        for i in 0..1000 {
+            while producer.push(black_box(i)).is_err() {}
+        }
+    }));
+
+    STOP_SIG.store(true, Ordering::Release);
+    handle.join().unwrap();
+}
+
+fn batched_bench(b: &mut Criterion) {
+    let queue: &'static mut StaticSpinQueue::<usize, 128> = Box::leak(Box::new(Default::default()));
+
+    STOP_SIG.store(false, Ordering::Release);
+
+    let consumer = queue.consumer().unwrap();
+    let handle = std::thread::spawn(move || {
+        let mut out = Vec::with_capacity(BATCH);
+        while !STOP_SIG.load(Ordering::Acquire) {
+            consumer.pop_batch(&mut out, BATCH);
+            black_box(&out);
+            out.clear();
+        }
+    });
+
+    let producer = queue.producer().unwrap();
+    let mut items = Vec::with_capacity(BATCH);
+    b.bench_function("Batched enqueue 1000", |b| b.iter(|| {
+        for i in 0..1000 {
+            items.push(black_box(i));
+            if items.len() == BATCH {
+                while !items.is_empty() {
+                    producer.push_slice(&mut items);
+                }
+            }
+        }
+
+        while !items.is_empty() {
+            producer.push_slice(&mut items);
+        }
+    }));
+
+    STOP_SIG.store(true, Ordering::Release);
+    handle.join().unwrap();
+}
+
+// The same SPSC workload twice, through plain handles and through buffered ones
+fn buffered_spsc_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Buffered SPSC 1000");
+    group.throughput(Throughput::Elements(1000));
+
+    {
+        let queue: &'static mut StaticSpinQueue::<usize, 128> = Box::leak(Box::new(Default::default()));
+
+        STOP_SIG.store(false, Ordering::Release);
+
+        let consumer = queue.consumer().unwrap();
+        let handle = std::thread::spawn(move || {
+            while !STOP_SIG.load(Ordering::Acquire) {
+                black_box(consumer.pop());
+            }
+        });
+
+        let producer = queue.producer().unwrap();
+        group.bench_function("Per item", |b| b.iter(|| {
+            for i in 0..1000 {
+                while producer.push(black_box(i)).is_err() {}
+            }
+        }));
+
+        STOP_SIG.store(true, Ordering::Release);
+        handle.join().unwrap();
+    }
+
+    {
+        let queue: &'static mut StaticSpinQueue::<usize, 128> = Box::leak(Box::new(Default::default()));
+
+        STOP_SIG.store(false, Ordering::Release);
+
+        let mut consumer: BufferedConsumer<_, BATCH> = BufferedConsumer::new(queue.consumer().unwrap());
+        let handle = std::thread::spawn(move || {
+            while !STOP_SIG.load(Ordering::Acquire) {
+                black_box(consumer.pop());
+            }
+            // Whatever was prefetched last is of no interest
+            drop(consumer.into_inner());
+        });
+
+        let mut producer: BufferedProducer<_, BATCH> = BufferedProducer::new(queue.producer().unwrap());
+        group.bench_function("Buffered", |b| b.iter(|| {
+            for i in 0..1000 {
+                while producer.push(black_box(i)).is_err() {}
+            }
+        }));
+
+        // Dropping the producer only flushes what fits right away, hand everything over while the consumer still runs
+        while producer.flush() != 0 {}
+        STOP_SIG.store(true, Ordering::Release);
+        handle.join().unwrap();
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, per_item_bench, batched_bench, buffered_spsc_bench);
+criterion_main!(benches);
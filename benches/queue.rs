@@ -1,12 +1,23 @@
-use criterion::{criterion_group, criterion_main, Criterion, black_box};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput, black_box};
 use core::sync::atomic::*;
 
-use queueue::queue::nonblocking::StaticSpinQueue;
+use queueue::queue::nonblocking::{StaticBackoffQueue, StaticQueue, StaticSpinQueue};
 use queueue::queue::nonblocking::Queue;
+#[cfg(feature = "alloc")]
+use queueue::queue::nonblocking::HeapQueue;
+use queueue::queue::sequencer::{HybridSpinSequencer, Sequencer, SpinSequencer};
+#[cfg(feature = "std")]
+use queueue::queue::sequencer::CondvarSequencer;
+#[cfg(all(target_os = "linux", not(feature = "loom")))]
+use queueue::queue::sequencer::FutexSequencer;
+use queueue::queue::backoff::{Backoff, ExponentialBackoff, NoBackoff, SpinBackoff};
+#[cfg(feature = "std")]
+use queueue::queue::backoff::YieldBackoff;
+use queueue::queue::spsc::SpscQueue;
 
 fn sp_enqueue_bench(b: &mut Criterion) {
     let mut queue: StaticSpinQueue::<usize, 16> = Default::default();
-    let mut producer = queue.producer();
+    let mut producer = queue.producer().unwrap();
     b.bench_function("Enqueue 1000", |b| b.iter(|| {
         for i in 0..1000 {
             let zero = black_box(0);
@@ -17,7 +28,7 @@ fn sp_enqueue_bench(b: &mut Criterion) {
 
 fn sc_dequeue_bench(b: &mut Criterion) {
     let mut queue: StaticSpinQueue::<usize, 16> = Default::default();
-    let mut consumer = queue.consumer();
+    let mut consumer = queue.consumer().unwrap();
     b.bench_function("Dequeue 1000", |b| b.iter(|| {
         for i in 0..1000 {
             consumer.pop();
@@ -25,20 +36,73 @@ fn sc_dequeue_bench(b: &mut Criterion) {
     }));
 }
 
+// Push then pop, single threaded, so the ticket-to-slot mapping dominates.
+// Power of two capacities map with a mask, others with a division, which HeapQueue can't fold into a constant.
+fn capacity_bench<Q: Queue<Item = usize>>(c: &mut Criterion, queue: &Q, name: &str) {
+    let mut group = c.benchmark_group("Push/pop 1000");
+    group.throughput(Throughput::Elements(1000));
+    group.bench_function(name, |b| b.iter(|| {
+        for i in 0..1000 {
+            black_box(queue.push(black_box(i))).unwrap();
+            black_box(queue.pop());
+        }
+    }));
+    group.finish();
+}
+
+fn capacity_benches(c: &mut Criterion) {
+    capacity_bench(c, &StaticSpinQueue::<usize, 16>::default(), "Static, 16");
+    capacity_bench(c, &StaticSpinQueue::<usize, 15>::default(), "Static, 15");
+    #[cfg(feature = "alloc")]
+    capacity_bench(c, &HeapQueue::<usize, SpinSequencer>::with_capacity(16), "Heap, 16");
+    #[cfg(feature = "alloc")]
+    capacity_bench(c, &HeapQueue::<usize, SpinSequencer>::with_capacity(15), "Heap, 15");
+}
+
 static stop_sig: AtomicBool = AtomicBool::new(false);
 
-fn mp_enqueue_bench(b: &mut Criterion) {
-    let queue: &'static mut StaticSpinQueue::<usize, 16> = Box::leak(Box::new(Default::default()));
+// Process CPU time in clock ticks, to report how busy each sequencer keeps the cores
+#[cfg(target_os = "linux")]
+fn cpu_ticks() -> Option<u64> {
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    // Fields after the parenthesized command name, utime and stime are the 14th and 15th overall
+    let rest = &stat[stat.rfind(')')? + 2..];
+    let mut fields = rest.split(' ').skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    Some(utime + stime)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cpu_ticks() -> Option<u64> {
+    None
+}
+
+// Run f, then print the cores kept busy on average. /proc reports in USER_HZ, which is 100 on Linux.
+fn report_cpu<F: FnOnce()>(label: &str, f: F) {
+    let start = std::time::Instant::now();
+    let before = cpu_ticks();
+    f();
+    let wall = start.elapsed().as_secs_f64();
+
+    if let (Some(before), Some(after)) = (before, cpu_ticks()) {
+        let cpu = (after - before) as f64 / 100.0;
+        println!("{}: {:.2} cores busy on average", label, cpu / wall);
+    }
+}
+
+fn mp_enqueue_bench<S: Sequencer + Send + Sync + 'static>(c: &mut Criterion, sequencer: &str) {
+    let queue: &'static StaticQueue::<usize, S, 16> = Box::leak(Box::new(Default::default()));
     const P_COUNT: usize = 4;
 
     stop_sig.store(false, Ordering::Release);
 
     let mut handles = Vec::new();
     for _ in 0..P_COUNT-1 {
-        let mut producer = queue.producer();
+        let producer = queue.producer().unwrap();
         let handle = std::thread::spawn(move || {
             while !stop_sig.load(Ordering::Acquire) {
-                for i in 0..1000 {
+                for _ in 0..1000 {
                     black_box(producer.push(black_box(0)));
                 }
             }
@@ -46,12 +110,17 @@ fn mp_enqueue_bench(b: &mut Criterion) {
         handles.push(handle);
     }
 
-    let mut producer = queue.producer();
-    b.bench_function("MP Enqueue 1000", |b| b.iter(|| {
-        for i in 0..1000 {
-            black_box(producer.push(black_box(0)));
-        }
-    }));
+    let producer = queue.producer().unwrap();
+    let mut group = c.benchmark_group("MP Enqueue 1000");
+    group.throughput(Throughput::Elements(1000));
+    report_cpu(&format!("MP Enqueue 1000/{}", sequencer), || {
+        group.bench_function(sequencer, |b| b.iter(|| {
+            for _ in 0..1000 {
+                black_box(producer.push(black_box(0)));
+            }
+        }));
+    });
+    group.finish();
 
     stop_sig.store(true, Ordering::Release);
     for handle in handles.into_iter() {
@@ -59,49 +128,231 @@ fn mp_enqueue_bench(b: &mut Criterion) {
     }
 }
 
-fn balenced_bench(b: &mut Criterion) {
-    let queue: &'static mut StaticSpinQueue::<usize, 16> = Box::leak(Box::new(Default::default()));
+thread_local!(static FAILED_CAS: std::cell::Cell<u64> = std::cell::Cell::new(0));
+
+// Counts the lost ticket CAS races of the calling thread, which is what backing off is meant to bring down.
+// Thread-local, so counting doesn't add contention of its own.
+#[derive(Default)]
+struct Counting<K: Backoff>(K);
+
+impl<K: Backoff> Backoff for Counting<K> {
+    fn backoff(&mut self) {
+        FAILED_CAS.with(|failed| failed.set(failed.get() + 1));
+        self.0.backoff();
+    }
+}
+
+fn take_failed_cas() -> u64 {
+    FAILED_CAS.with(|failed| failed.replace(0))
+}
+
+fn balenced_bench<S: Sequencer + Send + Sync + 'static, K: Backoff + 'static>(c: &mut Criterion, sequencer: &str) {
+    let queue: &'static StaticBackoffQueue::<usize, S, Counting<K>, 16> = Box::leak(Box::new(Default::default()));
     const PC_COUNT: usize = 4;
 
     stop_sig.store(false, Ordering::Release);
 
     let mut handles = Vec::new();
     for _ in 0..PC_COUNT-1 {
-        let mut producer = queue.producer();
+        let producer = queue.producer().unwrap();
         let handle = std::thread::spawn(move || {
+            let mut ops = 0;
             while !stop_sig.load(Ordering::Acquire) {
-                for i in 0..1000 {
+                for _ in 0..1000 {
                     black_box(producer.push(black_box(0)));
                 }
+                ops += 1000;
             }
+            (take_failed_cas(), ops)
         });
         handles.push(handle);
     }
 
     for _ in 0..PC_COUNT {
-        let mut consumer = queue.consumer();
+        let consumer = queue.consumer().unwrap();
         let handle = std::thread::spawn(move || {
+            let mut ops = 0;
             while !stop_sig.load(Ordering::Acquire) {
-                for i in 0..1000 {
+                for _ in 0..1000 {
                     black_box(consumer.pop());
                 }
+                ops += 1000;
             }
+            (take_failed_cas(), ops)
         });
         handles.push(handle);
     }
 
-    let mut producer = queue.producer();
-    b.bench_function("Balanced", |b| b.iter(|| {
-        for i in 0..1000 {
-            black_box(producer.push(black_box(0)));
+    let producer = queue.producer().unwrap();
+    let mut ops = 0;
+    take_failed_cas();
+    let mut group = c.benchmark_group("Balanced");
+    group.throughput(Throughput::Elements(1000));
+    report_cpu(&format!("Balanced/{}", sequencer), || {
+        group.bench_function(sequencer, |b| b.iter(|| {
+            for _ in 0..1000 {
+                black_box(producer.push(black_box(0)));
+            }
+            ops += 1000;
+        }));
+    });
+    group.finish();
+
+    stop_sig.store(true, Ordering::Release);
+    let mut failed = take_failed_cas();
+    for handle in handles.into_iter() {
+        let (f, o) = handle.join().unwrap();
+        failed += f;
+        ops += o;
+    }
+    println!("Balanced/{}: {:.3} failed ticket CAS attempts per push or pop", sequencer, failed as f64 / ops as f64);
+}
+
+fn spsc_bench<S: Sequencer + Send + Sync + 'static>(c: &mut Criterion, sequencer: &str) {
+    let queue: &'static StaticQueue::<usize, S, 128> = Box::leak(Box::new(Default::default()));
+
+    stop_sig.store(false, Ordering::Release);
+
+    let consumer = queue.consumer().unwrap();
+    let handle = std::thread::spawn(move || {
+        while !stop_sig.load(Ordering::Acquire) {
+            black_box(consumer.pop());
+        }
+    });
+
+    let producer = queue.producer().unwrap();
+    let mut group = c.benchmark_group("SPSC 1000");
+    group.throughput(Throughput::Elements(1000));
+    report_cpu(&format!("SPSC 1000/{}", sequencer), || {
+        group.bench_function(sequencer, |b| b.iter(|| {
+            for i in 0..1000 {
+                while producer.push(black_box(i)).is_err() {}
+            }
+        }));
+    });
+    group.finish();
+
+    stop_sig.store(true, Ordering::Release);
+    handle.join().unwrap();
+}
+
+// Same workload as spsc_bench, on the queue without tickets or sequencers
+fn spsc_queue_bench(c: &mut Criterion) {
+    let queue: &'static mut SpscQueue::<usize, 128> = Box::leak(Box::new(SpscQueue::new()));
+    let (mut producer, mut consumer) = queue.split();
+
+    stop_sig.store(false, Ordering::Release);
+
+    let handle = std::thread::spawn(move || {
+        while !stop_sig.load(Ordering::Acquire) {
+            black_box(consumer.pop());
+        }
+    });
+
+    let mut group = c.benchmark_group("SPSC 1000");
+    group.throughput(Throughput::Elements(1000));
+    report_cpu("SPSC 1000/SpscQueue", || {
+        group.bench_function("SpscQueue", |b| b.iter(|| {
+            for i in 0..1000 {
+                while producer.push(black_box(i)).is_err() {}
+            }
+        }));
+    });
+    group.finish();
+
+    stop_sig.store(true, Ordering::Release);
+    handle.join().unwrap();
+}
+
+// SPSC with more busy pairs than most machines have cores, so waiters regularly lose their core
+//   to a thread that isn't the one they are waiting for
+fn oversubscribed_spsc_bench<S: Sequencer + Send + Sync + 'static>(c: &mut Criterion, sequencer: &str) {
+    const PAIRS: usize = 8;
+
+    stop_sig.store(false, Ordering::Release);
+
+    let mut handles = Vec::new();
+    for _ in 0..PAIRS-1 {
+        let queue: &'static StaticQueue::<usize, S, 16> = Box::leak(Box::new(Default::default()));
+
+        let producer = queue.producer().unwrap();
+        handles.push(std::thread::spawn(move || {
+            while !stop_sig.load(Ordering::Acquire) {
+                for i in 0..1000 {
+                    black_box(producer.push(black_box(i)));
+                }
+            }
+        }));
+
+        let consumer = queue.consumer().unwrap();
+        handles.push(std::thread::spawn(move || {
+            while !stop_sig.load(Ordering::Acquire) {
+                black_box(consumer.pop());
+            }
+        }));
+    }
+
+    let queue: &'static StaticQueue::<usize, S, 16> = Box::leak(Box::new(Default::default()));
+    let consumer = queue.consumer().unwrap();
+    handles.push(std::thread::spawn(move || {
+        while !stop_sig.load(Ordering::Acquire) {
+            black_box(consumer.pop());
         }
     }));
 
+    let producer = queue.producer().unwrap();
+    let mut group = c.benchmark_group("Oversubscribed SPSC 1000");
+    group.throughput(Throughput::Elements(1000));
+    report_cpu(&format!("Oversubscribed SPSC 1000/{}", sequencer), || {
+        group.bench_function(sequencer, |b| b.iter(|| {
+            for i in 0..1000 {
+                while producer.push(black_box(i)).is_err() {}
+            }
+        }));
+    });
+    group.finish();
+
     stop_sig.store(true, Ordering::Release);
     for handle in handles.into_iter() {
         handle.join().unwrap();
     }
 }
 
-criterion_group!(benches, sp_enqueue_bench, sc_dequeue_bench, mp_enqueue_bench, balenced_bench);
+// The same workloads for every sequencer, so the numbers line up per group
+fn sequencer_benches(c: &mut Criterion) {
+    mp_enqueue_bench::<SpinSequencer>(c, "Spin");
+    #[cfg(feature = "std")]
+    mp_enqueue_bench::<CondvarSequencer>(c, "Condvar");
+    #[cfg(all(target_os = "linux", not(feature = "loom")))]
+    mp_enqueue_bench::<FutexSequencer>(c, "Futex");
+
+    balenced_bench::<SpinSequencer, NoBackoff>(c, "Spin");
+    #[cfg(feature = "std")]
+    balenced_bench::<CondvarSequencer, NoBackoff>(c, "Condvar");
+    #[cfg(all(target_os = "linux", not(feature = "loom")))]
+    balenced_bench::<FutexSequencer, NoBackoff>(c, "Futex");
+
+    spsc_bench::<SpinSequencer>(c, "Spin");
+    spsc_bench::<HybridSpinSequencer<128>>(c, "Hybrid spin");
+    #[cfg(feature = "std")]
+    spsc_bench::<CondvarSequencer>(c, "Condvar");
+    #[cfg(all(target_os = "linux", not(feature = "loom")))]
+    spsc_bench::<FutexSequencer>(c, "Futex");
+    spsc_queue_bench(c);
+
+    oversubscribed_spsc_bench::<SpinSequencer>(c, "Spin");
+    oversubscribed_spsc_bench::<HybridSpinSequencer<128>>(c, "Hybrid spin");
+    #[cfg(all(target_os = "linux", not(feature = "loom")))]
+    oversubscribed_spsc_bench::<FutexSequencer>(c, "Futex");
+}
+
+// 4 producers and 4 consumers fighting over the tickets, with each way of backing off a lost CAS
+fn backoff_benches(c: &mut Criterion) {
+    balenced_bench::<SpinSequencer, SpinBackoff>(c, "Spin, spin backoff");
+    balenced_bench::<SpinSequencer, ExponentialBackoff>(c, "Spin, exponential backoff");
+    #[cfg(feature = "std")]
+    balenced_bench::<SpinSequencer, YieldBackoff>(c, "Spin, yield backoff");
+}
+
+criterion_group!(benches, sp_enqueue_bench, sc_dequeue_bench, capacity_benches, sequencer_benches, backoff_benches);
 criterion_main!(benches);
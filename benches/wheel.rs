@@ -0,0 +1,77 @@
+use criterion::{criterion_group, criterion_main, Criterion, black_box};
+use core::sync::atomic::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+
+use queueue::timing_wheel::hierarchical::VecDequeWheel;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// Every advance crosses a level 1 boundary, so each one cascades a slot
+fn cascade(wheel: &mut VecDequeWheel<usize>) {
+    let base = wheel.elapsed();
+    for i in 0..16 {
+        wheel.schedule(base + 64 + i, i).unwrap();
+    }
+    wheel.fast_forward(base + 64, |item, _| { black_box(item); });
+    wheel.fast_forward(base + 128, |item, _| { black_box(item); });
+}
+
+fn cascade_bench(b: &mut Criterion) {
+    let mut wheel = VecDequeWheel::new(0);
+
+    // Warm up, so every slot touched below already has its buffer
+    for _ in 0..64 {
+        cascade(&mut wheel);
+    }
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..1000 {
+        cascade(&mut wheel);
+    }
+    let after = ALLOCATIONS.load(Ordering::Relaxed);
+    println!("Allocations per 1000 cascading advances: {}", after - before);
+
+    b.bench_function("Cascading advance", |b| b.iter(|| cascade(&mut wheel)));
+}
+
+// Called on every tick while the only timer is far away, the common case for a tick-driven loop
+fn idle_bench(b: &mut Criterion) {
+    let mut wheel = VecDequeWheel::new(0);
+    wheel.schedule(1 << 40, 0).unwrap();
+
+    b.bench_function("Idle fast_forward 1000", |b| b.iter(|| {
+        for _ in 0..1000 {
+            let next = wheel.elapsed() + 1;
+            wheel.fast_forward(next, |item, _| { black_box(item); });
+        }
+    }));
+
+    let mut wheel = VecDequeWheel::new(0);
+    wheel.schedule(1 << 40, 0).unwrap();
+
+    b.bench_function("Idle advance_if_due 1000", |b| b.iter(|| {
+        for _ in 0..1000 {
+            let next = wheel.elapsed() + 1;
+            black_box(wheel.advance_if_due(next, |item, _| { black_box(item); }));
+        }
+    }));
+}
+
+criterion_group!(benches, cascade_bench, idle_bench);
+criterion_main!(benches);
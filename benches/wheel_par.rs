@@ -0,0 +1,42 @@
+use criterion::{criterion_group, criterion_main, Criterion, black_box};
+
+use queueue::timing_wheel::hierarchical::VecDequeWheel;
+
+const COUNT: usize = 1024;
+
+// Stand-in for a CPU-bound timer callback
+fn expensive(item: usize) -> usize {
+    let mut acc = item;
+    for i in 0..10000 {
+        acc = acc.wrapping_mul(31).wrapping_add(i);
+    }
+    acc
+}
+
+fn fill(wheel: &mut VecDequeWheel<usize>) {
+    let base = wheel.elapsed();
+    for i in 0..COUNT {
+        wheel.schedule(base + i % 64 + 1, i).unwrap();
+    }
+}
+
+fn sequential_bench(b: &mut Criterion) {
+    let mut wheel = VecDequeWheel::new(0);
+    b.bench_function("Fast forward 1024 expensive", |b| b.iter(|| {
+        fill(&mut wheel);
+        let moment = wheel.elapsed() + 64;
+        wheel.fast_forward(moment, |item, _| { black_box(expensive(item)); });
+    }));
+}
+
+fn parallel_bench(b: &mut Criterion) {
+    let mut wheel = VecDequeWheel::new(0);
+    b.bench_function("Parallel fast forward 1024 expensive", |b| b.iter(|| {
+        fill(&mut wheel);
+        let moment = wheel.elapsed() + 64;
+        wheel.fast_forward_par(moment, |item, _| { black_box(expensive(item)); });
+    }));
+}
+
+criterion_group!(benches, sequential_bench, parallel_bench);
+criterion_main!(benches);
@@ -1,6 +1,11 @@
-#![feature(const_generics, maybe_uninit_uninit_array, internal_uninit_const, const_fn, const_in_array_repeat_expressions)]
-#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#![feature(const_generics, maybe_uninit_uninit_array, internal_uninit_const, const_fn, const_in_array_repeat_expressions, const_panic, const_if_match)]
+#![cfg_attr(not(any(feature = "std", feature = "loom", test)), no_std)]
 #![cfg_attr(test, feature(box_syntax))]
 
+#[cfg(any(feature = "alloc", test))]
+extern crate alloc;
+
 pub mod queue;
 pub mod timing_wheel;
+mod sync;
+mod util;
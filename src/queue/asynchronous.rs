@@ -0,0 +1,284 @@
+use super::nonblocking::{Consumer, Handles, Pop, Queue};
+
+use crate::sync::*;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+// Wraps a queue so consumers can await items, instead of spinning on pop in the executor.
+// Tasks that found it empty leave their waker here, and the next push wakes all of them to retry.
+// The waker list is only locked when someone registered, so push stays lock-free otherwise.
+pub struct AsyncQueue<Q: Queue> {
+    inner: Q,
+    waiting: AtomicBool,
+    locked: AtomicBool,
+    wakers: UnsafeCell<Vec<Waker>>,
+}
+
+// wakers is only touched with locked held
+unsafe impl<Q: Queue> Sync for AsyncQueue<Q> {}
+
+impl<Q: Queue> AsyncQueue<Q> {
+    pub fn new(inner: Q) -> Self {
+        Self {
+            inner,
+            waiting: AtomicBool::new(false),
+            locked: AtomicBool::new(false),
+            wakers: UnsafeCell::new(Vec::new()),
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.inner
+    }
+
+    // Critical sections are a handful of instructions, a spinlock is all it takes
+    fn with_wakers<R, F: FnOnce(&mut Vec<Waker>) -> R>(&self, f: F) -> R {
+        while self.locked.compare_and_swap(false, true, Ordering::Acquire) {
+            spin_loop_hint();
+        }
+        let result = self.wakers.with_mut(|wakers| f(unsafe { &mut *wakers }));
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+
+    // Ready(None) once the queue is closed and drained
+    pub fn poll_pop(&self, cx: &mut Context<'_>) -> Poll<Option<Q::Item>> {
+        match self.inner.try_pop() {
+            Pop::Item(t) => return Poll::Ready(Some(t)),
+            Pop::Closed => return Poll::Ready(None),
+            Pop::Empty => {}
+        }
+
+        self.with_wakers(|wakers| {
+            // Polled again without being woken, e.g. by a select, the waker is already in there
+            if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+            self.waiting.store(true, Ordering::Relaxed);
+        });
+
+        // Pairs with the fence in notify_item. Either the pusher sees us waiting,
+        //   or we see its item here, so a push between the failed pop and the registration can't be missed.
+        fence(Ordering::SeqCst);
+
+        match self.inner.try_pop() {
+            Pop::Item(t) => Poll::Ready(Some(t)),
+            Pop::Closed => Poll::Ready(None),
+            Pop::Empty => Poll::Pending,
+        }
+    }
+
+    pub fn pop_async(&self) -> PopFuture<'_, Q> {
+        PopFuture {
+            queue: self,
+        }
+    }
+
+    fn notify_item(&self) {
+        fence(Ordering::SeqCst);
+
+        if self.waiting.load(Ordering::Relaxed) {
+            let wakers = self.with_wakers(|wakers| {
+                self.waiting.store(false, Ordering::Relaxed);
+                core::mem::replace(wakers, Vec::new())
+            });
+
+            // Outside the lock, waking may run arbitrary executor code
+            for waker in wakers.into_iter() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<Q: Queue> Queue for AsyncQueue<Q> {
+    type Item = Q::Item;
+
+    fn push(&self, t: Self::Item) -> Result<(), Self::Item> {
+        self.inner.push(t)?;
+        self.notify_item();
+        Ok(())
+    }
+
+    fn push_notify<F: FnOnce()>(&self, t: Self::Item, on_became_nonempty: F) -> Result<(), Self::Item> {
+        self.inner.push_notify(t, on_became_nonempty)?;
+        self.notify_item();
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<Self::Item> {
+        self.inner.pop()
+    }
+
+    unsafe fn shared_peek(&self) -> Option<&Self::Item> {
+        self.inner.shared_peek()
+    }
+
+    fn push_slice(&self, items: &mut Vec<Self::Item>) -> usize {
+        let pushed = self.inner.push_slice(items);
+        if pushed != 0 {
+            self.notify_item();
+        }
+        pushed
+    }
+
+    fn pop_batch(&self, out: &mut Vec<Self::Item>, max: usize) -> usize {
+        self.inner.pop_batch(out, max)
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    // Waiting tasks are woken up and resolve to None once the queue is drained
+    fn close(&self) {
+        self.inner.close();
+        self.notify_item();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn handles(&self) -> &Handles {
+        self.inner.handles()
+    }
+}
+
+pub struct PopFuture<'a, Q: Queue> {
+    queue: &'a AsyncQueue<Q>,
+}
+
+impl<'a, Q: Queue> Future for PopFuture<'a, Q> {
+    type Output = Option<Q::Item>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.queue.poll_pop(cx)
+    }
+}
+
+impl<'a, Q: Queue> Consumer<'a, AsyncQueue<Q>> {
+    // Resolves to the next item, or None once the queue is closed and drained
+    pub fn pop_async(&self) -> PopFuture<'a, Q> {
+        self.queue().pop_async()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::nonblocking::StaticSpinQueue;
+
+    use std::sync::Arc;
+    use std::task::{RawWaker, RawWakerVTable};
+    use std::thread::Thread;
+
+    // Wakers unparking the thread that polls, enough of an executor to drive one future
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake, wake_by_ref, drop_waker);
+
+    unsafe fn clone_waker(ptr: *const ()) -> RawWaker {
+        let thread = Arc::from_raw(ptr as *const Thread);
+        let cloned = thread.clone();
+        core::mem::forget(thread);
+        RawWaker::new(Arc::into_raw(cloned) as *const (), &VTABLE)
+    }
+
+    unsafe fn wake(ptr: *const ()) {
+        Arc::from_raw(ptr as *const Thread).unpark();
+    }
+
+    unsafe fn wake_by_ref(ptr: *const ()) {
+        (*(ptr as *const Thread)).unpark();
+    }
+
+    unsafe fn drop_waker(ptr: *const ()) {
+        drop(Arc::from_raw(ptr as *const Thread));
+    }
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        let mut f = Box::pin(f);
+        let thread = Arc::new(std::thread::current());
+        let waker = unsafe { Waker::from_raw(RawWaker::new(Arc::into_raw(thread) as *const (), &VTABLE)) };
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            match f.as_mut().poll(&mut cx) {
+                Poll::Ready(t) => break t,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn pop_async() {
+        let queue: AsyncQueue<StaticSpinQueue<usize, 4>> = AsyncQueue::new(Default::default());
+        let queue = Box::leak(Box::new(queue));
+
+        let consumer = queue.consumer().unwrap();
+        let cth = std::thread::spawn(move || block_on(consumer.pop_async()));
+
+        // Only push once the consumer is parked on the empty queue
+        while !queue.waiting.load(Ordering::Relaxed) {
+            std::thread::yield_now();
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        queue.producer().unwrap().push(42).unwrap();
+
+        assert_eq!(cth.join().unwrap(), Some(42));
+        assert!(!queue.waiting.load(Ordering::Relaxed));
+
+        queue.push(1).unwrap();
+        assert_eq!(block_on(queue.pop_async()), Some(1));
+    }
+
+    #[test]
+    fn pop_async_closed() {
+        let queue: AsyncQueue<StaticSpinQueue<usize, 4>> = AsyncQueue::new(Default::default());
+        let queue = Box::leak(Box::new(queue));
+
+        queue.push(0).unwrap();
+        let consumer = queue.consumer().unwrap();
+        let cth = std::thread::spawn(move || {
+            let first = block_on(consumer.pop_async());
+            (first, block_on(consumer.pop_async()))
+        });
+
+        while !queue.waiting.load(Ordering::Relaxed) {
+            std::thread::yield_now();
+        }
+        queue.close();
+
+        assert_eq!(cth.join().unwrap(), (Some(0), None));
+    }
+
+    #[test]
+    fn pop_async_stress() {
+        const COUNT: usize = 100000;
+
+        let queue: AsyncQueue<StaticSpinQueue<usize, 4>> = AsyncQueue::new(Default::default());
+        let queue = Box::leak(Box::new(queue));
+
+        let producer = queue.producer().unwrap();
+        let pth = std::thread::spawn(move || {
+            for i in 0..COUNT {
+                while producer.push(i).is_err() {
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        // A lost wakeup leaves the consumer parked for good
+        let consumer = queue.consumer().unwrap();
+        for i in 0..COUNT {
+            assert_eq!(block_on(consumer.pop_async()), Some(i));
+        }
+
+        pth.join().unwrap();
+    }
+}
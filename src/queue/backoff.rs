@@ -0,0 +1,78 @@
+use crate::sync::spin_loop_hint;
+
+// What a ticket CAS loop does after losing a race, before it retries.
+// A fresh value is made for every push or pop, so implementations can escalate over the retries of one call.
+pub trait Backoff: Default {
+    fn backoff(&mut self);
+}
+
+// Retry right away, the tightest loop and the most cache-line traffic under contention
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoBackoff;
+
+impl Backoff for NoBackoff {
+    #[inline(always)]
+    fn backoff(&mut self) {}
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SpinBackoff;
+
+impl Backoff for SpinBackoff {
+    fn backoff(&mut self) {
+        spin_loop_hint();
+    }
+}
+
+// Spins twice as long after every lost race, up to 2^MAX_STEP hints.
+// Spreads out contending threads, so fewer of them fight over the ticket at once.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExponentialBackoff {
+    step: u32,
+}
+
+impl ExponentialBackoff {
+    const MAX_STEP: u32 = 6;
+}
+
+impl Backoff for ExponentialBackoff {
+    fn backoff(&mut self) {
+        for _ in 0..1u32 << self.step {
+            spin_loop_hint();
+        }
+
+        if self.step < Self::MAX_STEP {
+            self.step += 1;
+        }
+    }
+}
+
+// Gives the core away, for more contending threads than cores,
+//   where the thread holding up the others may be the one that isn't running
+#[cfg(any(feature = "std", test))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct YieldBackoff;
+
+#[cfg(any(feature = "std", test))]
+impl Backoff for YieldBackoff {
+    fn backoff(&mut self) {
+        std::thread::yield_now();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exponential() {
+        let mut backoff = ExponentialBackoff::default();
+        for step in 0..ExponentialBackoff::MAX_STEP {
+            assert_eq!(backoff.step, step);
+            backoff.backoff();
+        }
+
+        backoff.backoff();
+        assert_eq!(backoff.step, ExponentialBackoff::MAX_STEP);
+    }
+}
@@ -0,0 +1,259 @@
+use super::nonblocking::{Consumer, Handles, Producer, Queue};
+use super::credit::CreditQueue;
+
+use crate::sync::*;
+use std::sync::{Condvar, Mutex};
+
+// Wraps a queue so producers can park while it's full, instead of spinning on Err.
+// Pops notify parked producers through a condvar, which is separate from the per-slot sequencers.
+// The condvar is only touched when someone is actually waiting, so pop stays lock-free otherwise.
+pub struct BlockingQueue<Q: Queue> {
+    inner: Q,
+    waiting: AtomicUsize,
+    lock: Mutex<()>,
+    space: Condvar,
+}
+
+impl<Q: Queue> BlockingQueue<Q> {
+    pub fn new(inner: Q) -> Self {
+        Self {
+            inner,
+            waiting: AtomicUsize::new(0),
+            lock: Mutex::new(()),
+            space: Condvar::new(),
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.inner
+    }
+
+    // Err only if the queue is closed, before or while waiting
+    pub fn blocking_push(&self, t: Q::Item) -> Result<(), Q::Item> {
+        let mut t = match self.inner.push(t) {
+            Ok(()) => return Ok(()),
+            Err(t) => t,
+        };
+
+        let mut guard = self.lock.lock().unwrap();
+        self.waiting.fetch_add(1, Ordering::SeqCst);
+
+        let result = loop {
+            // Pairs with the fence in notify_space. Either the popper sees us waiting,
+            //   or we see the slot it freed here, so a pop between the failed push and the wait can't be missed.
+            fence(Ordering::SeqCst);
+
+            t = match self.inner.push(t) {
+                Ok(()) => break Ok(()),
+                Err(t) => t,
+            };
+
+            if self.inner.is_closed() {
+                break Err(t);
+            }
+
+            guard = self.space.wait(guard).unwrap();
+        };
+
+        self.waiting.fetch_sub(1, Ordering::Relaxed);
+        result
+    }
+
+    fn notify_space(&self) {
+        fence(Ordering::SeqCst);
+
+        if self.waiting.load(Ordering::Relaxed) != 0 {
+            // Taking the lock makes sure the waiter is either parked already, or yet to retry its push
+            let _guard = self.lock.lock().unwrap();
+            self.space.notify_all();
+        }
+    }
+}
+
+impl<Q: Queue> Queue for BlockingQueue<Q> {
+    type Item = Q::Item;
+
+    fn push(&self, t: Self::Item) -> Result<(), Self::Item> {
+        self.inner.push(t)
+    }
+
+    fn push_notify<F: FnOnce()>(&self, t: Self::Item, on_became_nonempty: F) -> Result<(), Self::Item> {
+        self.inner.push_notify(t, on_became_nonempty)
+    }
+
+    fn pop(&self) -> Option<Self::Item> {
+        let result = self.inner.pop()?;
+        self.notify_space();
+        Some(result)
+    }
+
+    unsafe fn shared_peek(&self) -> Option<&Self::Item> {
+        self.inner.shared_peek()
+    }
+
+    fn push_slice(&self, items: &mut Vec<Self::Item>) -> usize {
+        self.inner.push_slice(items)
+    }
+
+    fn pop_batch(&self, out: &mut Vec<Self::Item>, max: usize) -> usize {
+        let popped = self.inner.pop_batch(out, max);
+        if popped != 0 {
+            self.notify_space();
+        }
+        popped
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    // Parked producers are woken up and give up
+    fn close(&self) {
+        self.inner.close();
+        self.notify_space();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn handles(&self) -> &Handles {
+        self.inner.handles()
+    }
+}
+
+impl<Q: Queue> BlockingQueue<CreditQueue<Q>> {
+    // Granting makes room just like a pop does, so parked producers are woken up
+    pub fn grant(&self, n: usize) {
+        self.inner.grant(n);
+        self.notify_space();
+    }
+}
+
+impl<'a, Q: Queue> Consumer<'a, BlockingQueue<CreditQueue<Q>>> {
+    pub fn grant(&self, n: usize) {
+        self.queue().grant(n)
+    }
+}
+
+impl<'a, Q: Queue> Producer<'a, BlockingQueue<Q>> {
+    // Parks until a consumer frees a slot, Err if the queue gets closed
+    pub fn blocking_push(&self, data: Q::Item) -> Result<(), Q::Item> {
+        self.queue().blocking_push(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::nonblocking::{Pop, StaticSpinQueue};
+
+    #[test]
+    fn blocking_push() {
+        const COUNT: usize = 256;
+
+        let queue: BlockingQueue<StaticSpinQueue<usize, 1>> = BlockingQueue::new(Default::default());
+        let queue = Box::leak(Box::new(queue));
+
+        let producer = queue.producer().unwrap();
+        let consumer = queue.consumer().unwrap();
+
+        let pth = std::thread::spawn(move || {
+            for i in 0..COUNT {
+                producer.blocking_push(i).unwrap();
+            }
+        });
+
+        let cth = std::thread::spawn(move || {
+            for i in 0..COUNT {
+                if i % 16 == 0 {
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                }
+
+                loop {
+                    match consumer.pop() {
+                        None => std::thread::yield_now(),
+                        Some(j) if j == i => break,
+                        Some(j) => panic!("Unexpected item {}. Was waiting for {}.", j, i),
+                    }
+                }
+            }
+        });
+
+        pth.join().unwrap();
+        cth.join().unwrap();
+
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.waiting.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn blocking_credit() {
+        const COUNT: usize = 12;
+
+        let queue: BlockingQueue<CreditQueue<StaticSpinQueue<usize, 16>>> = BlockingQueue::new(CreditQueue::new(Default::default()));
+        let queue = Box::leak(Box::new(queue));
+        let pushed = Box::leak(Box::new(AtomicUsize::new(0)));
+
+        let mut pths = Vec::new();
+        for p in 0..2 {
+            let producer = queue.producer().unwrap();
+            let pushed = &*pushed;
+            pths.push(std::thread::spawn(move || {
+                for i in 0..COUNT / 2 {
+                    producer.blocking_push(p * COUNT + i).unwrap();
+                    pushed.fetch_add(1, Ordering::AcqRel);
+                }
+            }));
+        }
+
+        // Nothing gets through without credit
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        assert_eq!(pushed.load(Ordering::Acquire), 0);
+        assert!(queue.is_empty());
+
+        let consumer = queue.consumer().unwrap();
+        for granted in [3, 8, 12].iter() {
+            consumer.grant(granted - pushed.load(Ordering::Acquire));
+            while pushed.load(Ordering::Acquire) != *granted {
+                std::thread::yield_now();
+            }
+
+            // And not a single push more than granted
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            assert_eq!(pushed.load(Ordering::Acquire), *granted);
+            assert_eq!(queue.len(), *granted);
+        }
+
+        for p in pths.into_iter() {
+            p.join().unwrap();
+        }
+        assert_eq!(queue.inner().credit(), 0);
+        assert_eq!(consumer.drain().count(), COUNT);
+    }
+
+    #[test]
+    fn blocking_push_closed() {
+        let queue: BlockingQueue<StaticSpinQueue<usize, 1>> = BlockingQueue::new(Default::default());
+        let queue = Box::leak(Box::new(queue));
+
+        queue.push(0).unwrap();
+
+        let producer = queue.producer().unwrap();
+        let pth = std::thread::spawn(move || producer.blocking_push(1));
+
+        while queue.waiting.load(Ordering::Relaxed) == 0 {
+            std::thread::yield_now();
+        }
+        queue.close();
+
+        assert_eq!(pth.join().unwrap(), Err(1));
+        assert_eq!(queue.blocking_push(2), Err(2));
+        assert_eq!(queue.pop(), Some(0));
+        assert_eq!(queue.try_pop(), Pop::Closed);
+    }
+}
@@ -0,0 +1,294 @@
+use super::nonblocking::{Consumer, Producer, Queue};
+
+use alloc::vec::Vec;
+
+// Items a buffered handle was still holding when it was taken apart, oldest first
+pub struct Leftovers<T> {
+    items: alloc::vec::IntoIter<T>,
+}
+
+impl<T> Iterator for Leftovers<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.items.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.items.size_hint()
+    }
+}
+
+impl<T> ExactSizeIterator for Leftovers<T> {}
+
+impl<T> core::fmt::Debug for Leftovers<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Leftovers").field("len", &self.items.len()).finish()
+    }
+}
+
+// Stages pushes locally and hands them over to the queue once B items are collected,
+//   taking the tickets for all of them at once through push_slice.
+// Dropping it flushes once, without waiting for room, and drops whatever the queue doesn't take right then.
+//   To keep those items, take them back through into_inner instead.
+pub struct BufferedProducer<'a, Q: Queue, const B: usize> {
+    producer: Producer<'a, Q>,
+    // Oldest first, never more than B
+    buf: Vec<Q::Item>,
+}
+
+impl<'a, Q: Queue, const B: usize> BufferedProducer<'a, Q, B> {
+    // Fails the build when a producer without room to buffer anything is instantiated
+    const NONEMPTY: () = assert!(B > 0, "A BufferedProducer buffers at least one item");
+
+    pub fn new(producer: Producer<'a, Q>) -> Self {
+        let _ = Self::NONEMPTY;
+
+        Self {
+            producer,
+            buf: Vec::with_capacity(B),
+        }
+    }
+
+    // Err only if the buffer is full and the queue can't take any of it
+    pub fn push(&mut self, data: Q::Item) -> Result<(), Q::Item> {
+        if self.buf.len() == B && self.flush() == B {
+            return Err(data);
+        }
+
+        self.buf.push(data);
+        if self.buf.len() == B {
+            self.flush();
+        }
+        Ok(())
+    }
+
+    // Hand buffered items over in order, returns how many are still buffered
+    pub fn flush(&mut self) -> usize {
+        // Each round reserves a run of tickets as long as the free space, until the queue stops taking any
+        while !self.buf.is_empty() && self.producer.push_slice(&mut self.buf) != 0 {}
+
+        self.buf.len()
+    }
+
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    // Flushes what the queue takes right now, and gives back the producer along with the items it didn't take
+    pub fn into_inner(mut self) -> (Producer<'a, Q>, Leftovers<Q::Item>) {
+        self.flush();
+
+        let this = core::mem::ManuallyDrop::new(self);
+        // Moved out exactly once, this is forgotten right after and never dropped
+        let producer = unsafe { core::ptr::read(&this.producer) };
+        let buf = unsafe { core::ptr::read(&this.buf) };
+
+        (producer, Leftovers { items: buf.into_iter() })
+    }
+}
+
+impl<'a, Q: Queue, const B: usize> Drop for BufferedProducer<'a, Q, B> {
+    fn drop(&mut self) {
+        // Waiting for room could spin forever with no consumer around, or with the queue closed
+        self.flush();
+    }
+}
+
+// Prefetches up to B items at once when its local buffer runs dry, taking their tickets at once through pop_batch.
+// Prefetched items can't be pushed back, they would end up behind everything queued since, and may not fit anymore.
+//   Dropping it drops the prefetched items not popped yet, into_inner hands them back instead.
+pub struct BufferedConsumer<'a, Q: Queue, const B: usize> {
+    consumer: Consumer<'a, Q>,
+    // Newest first, so the next item comes off the back
+    buf: Vec<Q::Item>,
+}
+
+impl<'a, Q: Queue, const B: usize> BufferedConsumer<'a, Q, B> {
+    // Fails the build when a consumer without room to prefetch anything is instantiated
+    const NONEMPTY: () = assert!(B > 0, "A BufferedConsumer prefetches at least one item");
+
+    pub fn new(consumer: Consumer<'a, Q>) -> Self {
+        let _ = Self::NONEMPTY;
+
+        Self {
+            consumer,
+            buf: Vec::with_capacity(B),
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<Q::Item> {
+        if self.buf.is_empty() {
+            self.consumer.pop_batch(&mut self.buf, B);
+            self.buf.reverse();
+        }
+
+        self.buf.pop()
+    }
+
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    // Gives back the consumer along with the prefetched items not popped yet, which come before anything left in the queue
+    pub fn into_inner(self) -> (Consumer<'a, Q>, Leftovers<Q::Item>) {
+        let Self { consumer, mut buf } = self;
+
+        buf.reverse();
+        (consumer, Leftovers { items: buf.into_iter() })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::nonblocking::StaticSpinQueue;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn buffered() {
+        let queue: StaticSpinQueue<usize, 8> = Default::default();
+        let mut producer: BufferedProducer<_, 4> = BufferedProducer::new(queue.producer().unwrap());
+        let mut consumer: BufferedConsumer<_, 3> = BufferedConsumer::new(queue.consumer().unwrap());
+
+        producer.push(0).unwrap();
+        producer.push(1).unwrap();
+        producer.push(2).unwrap();
+        assert_eq!(producer.buffered(), 3);
+        assert_eq!(consumer.pop(), None);
+
+        // Fourth push fills the buffer and flushes it
+        producer.push(3).unwrap();
+        assert_eq!(producer.buffered(), 0);
+
+        for i in 4..12 {
+            producer.push(i).unwrap();
+        }
+        // 8 in the queue, 4 stuck in the buffer
+        assert_eq!(producer.buffered(), 4);
+        assert_eq!(producer.push(12), Err(12));
+
+        assert_eq!(consumer.pop(), Some(0));
+        assert_eq!(consumer.buffered(), 2);
+        assert_eq!(producer.flush(), 1);
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(producer.flush(), 1);
+        assert_eq!(consumer.pop(), Some(3));
+        assert_eq!(producer.flush(), 0);
+
+        producer.push(12).unwrap();
+        let mut popped = 4;
+        while let Some(i) = consumer.pop() {
+            assert_eq!(i, popped);
+            popped += 1;
+            producer.flush();
+        }
+        assert_eq!(popped, 13);
+
+        // Dropping flushes the producer, the consumer hands back what it prefetched
+        producer.push(13).unwrap();
+        producer.push(14).unwrap();
+        producer.push(15).unwrap();
+        drop(producer);
+        assert_eq!(consumer.pop(), Some(13));
+        assert_eq!(consumer.buffered(), 2);
+        let (consumer, leftovers) = consumer.into_inner();
+        assert_eq!(leftovers.collect::<Vec<_>>(), [14, 15]);
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn nothing_lost() {
+        let queue: StaticSpinQueue<usize, 2> = Default::default();
+
+        // Full queue and nobody popping, the producer hands back what didn't fit instead of waiting for room
+        let mut producer: BufferedProducer<_, 4> = BufferedProducer::new(queue.producer().unwrap());
+        for i in 0..3 {
+            producer.push(i).unwrap();
+        }
+        let (_, leftovers) = producer.into_inner();
+        assert_eq!(leftovers.collect::<Vec<_>>(), [2]);
+        assert_eq!(queue.len(), 2);
+
+        let mut consumer: BufferedConsumer<_, 4> = BufferedConsumer::new(queue.consumer().unwrap());
+        assert_eq!(consumer.pop(), Some(0));
+        assert_eq!(consumer.buffered(), 1);
+        assert!(queue.is_empty());
+        let (_, leftovers) = consumer.into_inner();
+        assert_eq!(leftovers.collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn dropped_with_leftovers() {
+        let item = Arc::new(());
+        let queue: StaticSpinQueue<Arc<()>, 2> = Default::default();
+
+        // The queue takes two, the third is dropped along with the producer
+        let mut producer: BufferedProducer<_, 4> = BufferedProducer::new(queue.producer().unwrap());
+        for _ in 0..3 {
+            producer.push(item.clone()).unwrap();
+        }
+        drop(producer);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(Arc::strong_count(&item), 3);
+
+        // Both are prefetched, the one not popped goes away with the consumer
+        let mut consumer: BufferedConsumer<_, 4> = BufferedConsumer::new(queue.consumer().unwrap());
+        drop(consumer.pop());
+        drop(consumer);
+        assert!(queue.is_empty());
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn closed_leftovers() {
+        let queue: StaticSpinQueue<usize, 2> = Default::default();
+        let mut producer: BufferedProducer<_, 4> = BufferedProducer::new(queue.producer().unwrap());
+
+        for i in 0..3 {
+            producer.push(i).unwrap();
+        }
+        queue.close();
+
+        // Nothing gets through anymore, the buffered items come back instead of vanishing
+        let (producer, leftovers) = producer.into_inner();
+        assert_eq!(leftovers.collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(producer.push(3), Err(3));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn buffered_spsc() {
+        const RANGE: core::ops::Range<usize> = 0usize..1048576usize;
+
+        let queue: Box<StaticSpinQueue<usize, 128>> = Default::default();
+        let queue = Box::leak(queue);
+
+        let mut producer: BufferedProducer<_, 16> = BufferedProducer::new(queue.producer().unwrap());
+        let mut consumer: BufferedConsumer<_, 16> = BufferedConsumer::new(queue.consumer().unwrap());
+
+        let pth = std::thread::spawn(move || {
+            for i in RANGE {
+                while producer.push(i).is_err() {}
+            }
+            while producer.flush() != 0 {}
+        });
+
+        let cth = std::thread::spawn(move || {
+            for i in RANGE {
+                loop {
+                    match consumer.pop() {
+                        None => continue,
+                        Some(j) if j == i => break,
+                        Some(j) => panic!("Unexpected item {}. Was waiting for {}.", j, i),
+                    }
+                }
+            }
+        });
+
+        pth.join().unwrap();
+        cth.join().unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+use super::nonblocking::{Consumer, Handles, Queue};
+
+use crate::sync::*;
+
+// Pull-based backpressure: every push spends one credit, and only consumers hand out credits with grant().
+// Pops don't give credits back, so producers can push exactly as much as was granted, on top of the usual capacity limit.
+// push fails while out of credit. Wrap it in a BlockingQueue to have producers park instead,
+//   and grant through the BlockingQueue so they get woken up.
+pub struct CreditQueue<Q: Queue> {
+    inner: Q,
+    credit: AtomicUsize,
+}
+
+impl<Q: Queue> CreditQueue<Q> {
+    pub fn new(inner: Q) -> Self {
+        Self {
+            inner,
+            credit: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.inner
+    }
+
+    pub fn credit(&self) -> usize {
+        self.credit.load(Ordering::Acquire)
+    }
+
+    pub fn grant(&self, n: usize) {
+        self.credit.fetch_add(n, Ordering::AcqRel);
+    }
+
+    fn take_credit(&self) -> bool {
+        loop {
+            let cur = self.credit.load(Ordering::Acquire);
+            if cur == 0 {
+                break false;
+            }
+
+            if self.credit.compare_and_swap(cur, cur - 1, Ordering::AcqRel) == cur {
+                break true;
+            }
+        }
+    }
+}
+
+impl<Q: Queue> Queue for CreditQueue<Q> {
+    type Item = Q::Item;
+
+    fn push(&self, t: Self::Item) -> Result<(), Self::Item> {
+        self.push_notify(t, || {})
+    }
+
+    fn push_notify<F: FnOnce()>(&self, t: Self::Item, on_became_nonempty: F) -> Result<(), Self::Item> {
+        if !self.take_credit() {
+            return Err(t);
+        }
+
+        self.inner.push_notify(t, on_became_nonempty).map_err(|t| {
+            // Queue is full or closed, the credit isn't spent
+            self.grant(1);
+            t
+        })
+    }
+
+    fn pop(&self) -> Option<Self::Item> {
+        self.inner.pop()
+    }
+
+    unsafe fn shared_peek(&self) -> Option<&Self::Item> {
+        self.inner.shared_peek()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    fn close(&self) {
+        self.inner.close()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn handles(&self) -> &Handles {
+        self.inner.handles()
+    }
+}
+
+impl<'a, Q: Queue> Consumer<'a, CreditQueue<Q>> {
+    pub fn grant(&self, n: usize) {
+        self.queue().grant(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::nonblocking::StaticSpinQueue;
+
+    #[test]
+    fn credit() {
+        let queue: CreditQueue<StaticSpinQueue<usize, 4>> = CreditQueue::new(Default::default());
+        let producer = queue.producer().unwrap();
+        let consumer = queue.consumer().unwrap();
+
+        assert_eq!(producer.push(0), Err(0));
+
+        consumer.grant(6);
+        for i in 0..4 {
+            producer.push(i).unwrap();
+        }
+
+        // Full, the credit stays around
+        assert_eq!(producer.push(4), Err(4));
+        assert_eq!(queue.credit(), 2);
+
+        assert_eq!(consumer.pop(), Some(0));
+        producer.push(4).unwrap();
+        assert_eq!(consumer.pop(), Some(1));
+        producer.push(5).unwrap();
+
+        // Popping frees room, but not credit
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(producer.push(6), Err(6));
+        assert_eq!(queue.credit(), 0);
+
+        // The default push_slice, cut short by the credit. The rest stays in the caller's buffer.
+        consumer.grant(1);
+        let mut items = Vec::with_capacity(16);
+        items.extend(6..9);
+        assert_eq!(queue.push_slice(&mut items), 1);
+        assert_eq!(items, vec![7, 8]);
+        assert_eq!(items.capacity(), 16);
+    }
+}
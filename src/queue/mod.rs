@@ -1,3 +1,12 @@
 pub mod nonblocking;
-mod sequencer;
+pub mod spsc;
+#[cfg(any(feature = "alloc", test))]
+pub mod buffered;
+pub mod credit;
+#[cfg(any(feature = "std", test))]
+pub mod blocking;
+#[cfg(any(feature = "alloc", test))]
+pub mod asynchronous;
+pub mod sequencer;
+pub mod backoff;
 mod slot;
@@ -1,177 +1,1814 @@
 use super::slot::Slot;
-use super::sequencer::Sequencer;
+use super::sequencer::{Sequencer, TimeoutSequencer};
+use super::backoff::{Backoff, NoBackoff};
 
-use core::sync::atomic::*;
+use crate::util::init_array;
+
+use crate::sync::*;
 use core::result::Result;
-use core::mem::MaybeUninit;
+
+#[cfg(any(feature = "alloc", test))]
+use alloc::sync::Arc;
+#[cfg(any(feature = "alloc", test))]
+use alloc::vec::Vec;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Pop<T> {
+    Item(T),
+    Empty,
+    // Closed and drained, nothing is ever coming again
+    Closed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handle {
+    Producer,
+    Consumer,
+}
+
+// Live Producer/Consumer handles of a queue. Handles register themselves when created and deregister when dropped,
+//   which fails once their kind has max live. Nothing else can touch the counts, so they always match the live handles.
+pub struct Handles {
+    producers: AtomicUsize,
+    consumers: AtomicUsize,
+    max: AtomicUsize,
+}
+
+impl Handles {
+    pub fn new() -> Self {
+        Self {
+            producers: AtomicUsize::new(0),
+            consumers: AtomicUsize::new(0),
+            max: AtomicUsize::new(usize::MAX),
+        }
+    }
+
+    pub fn count(&self, kind: Handle) -> usize {
+        self.counter(kind).load(Ordering::Acquire)
+    }
+
+    // Caps the live handles of each kind at n. Handles already live above the cap are left alone,
+    //   new ones are refused until enough of them are dropped.
+    pub fn set_max(&self, n: usize) {
+        self.max.store(n, Ordering::Release);
+    }
+
+    fn counter(&self, kind: Handle) -> &AtomicUsize {
+        match kind {
+            Handle::Producer => &self.producers,
+            Handle::Consumer => &self.consumers,
+        }
+    }
+
+    fn acquire(&self, kind: Handle) -> bool {
+        let counter = self.counter(kind);
+        loop {
+            let cur = counter.load(Ordering::Acquire);
+            if cur >= self.max.load(Ordering::Acquire) {
+                break false;
+            }
+
+            if counter.compare_and_swap(cur, cur + 1, Ordering::AcqRel) == cur {
+                break true;
+            }
+        }
+    }
+
+    // Only called by the drop of a handle that acquired, so it never goes below zero
+    fn release(&self, kind: Handle) {
+        let prev = self.counter(kind).fetch_sub(1, Ordering::AcqRel);
+        debug_assert!(prev > 0);
+    }
+}
+
+impl Default for Handles {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for Handles {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Handles")
+            .field("producers", &self.count(Handle::Producer))
+            .field("consumers", &self.count(Handle::Consumer))
+            .field("max", &self.max.load(Ordering::Relaxed))
+            .finish()
+    }
+}
 
 pub trait Queue: Send + Sync {
     type Item;
 
-    unsafe fn unsync_push(&self, t: Self::Item) -> Result<(), Self::Item> {
-        self.push(t)
+    unsafe fn unsync_push(&self, t: Self::Item) -> Result<(), Self::Item> {
+        self.push(t)
+    }
+    unsafe fn unsync_pop(&self) -> Option<Self::Item> {
+        self.pop()
+    }
+
+    fn push(&self, t: Self::Item) -> Result<(), Self::Item>;
+    fn pop(&self) -> Option<Self::Item>;
+
+    // The item the next pop would return, left in place. Always None for queues that can't look ahead without popping,
+    //   which is what implementors get unless they override it.
+    // Safety: nothing may pop while the reference is alive, SpscConsumer is the safe way to get one.
+    unsafe fn shared_peek(&self) -> Option<&Self::Item> {
+        None
+    }
+
+    // Only a snapshot, other threads may push or pop right after it's taken
+    fn len(&self) -> usize;
+    fn capacity(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Refuse all pushes from now on. Items already in the queue can still be popped.
+    // Once is_closed returns true no push can land anymore, which is what makes Pop::Closed final.
+    fn close(&self);
+    fn is_closed(&self) -> bool;
+
+    // Where this queue's Producer/Consumer handles are counted. Wrappers hand out their inner queue's,
+    //   so handles to the wrapper and to the inner queue share one cap.
+    fn handles(&self) -> &Handles;
+
+    // Like pop, but tells "empty for now" apart from "closed and drained"
+    fn try_pop(&self) -> Pop<Self::Item> {
+        if let Some(t) = self.pop() {
+            return Pop::Item(t);
+        }
+
+        if !self.is_closed() {
+            return Pop::Empty;
+        }
+
+        // Something may have been pushed between the failed pop and the close
+        match self.pop() {
+            Some(t) => Pop::Item(t),
+            None => Pop::Closed,
+        }
+    }
+
+    // Push, and call on_became_nonempty if this push took the queue from empty to non-empty,
+    //   so edge-triggered consumers are only woken up once per transition.
+    // The default can't tell, so it calls it on every successful push.
+    fn push_notify<F: FnOnce()>(&self, t: Self::Item, on_became_nonempty: F) -> Result<(), Self::Item> {
+        self.push(t)?;
+        on_became_nonempty();
+        Ok(())
+    }
+
+    // Pushes from the front of items until the queue is full, and removes the ones accepted.
+    // Returns how many were pushed.
+    #[cfg(any(feature = "alloc", test))]
+    fn push_slice(&self, items: &mut Vec<Self::Item>) -> usize {
+        // Taken from the back of the reversed Vec, so the caller's buffer keeps its capacity
+        let mut pushed = 0;
+        items.reverse();
+
+        while let Some(item) = items.pop() {
+            if let Err(item) = self.push(item) {
+                items.push(item);
+                break;
+            }
+            pushed += 1;
+        }
+
+        items.reverse();
+        pushed
+    }
+
+    // Appends at most max items to out, returns how many were popped
+    #[cfg(any(feature = "alloc", test))]
+    fn pop_batch(&self, out: &mut Vec<Self::Item>, max: usize) -> usize {
+        let mut popped = 0;
+        while popped < max {
+            match self.pop() {
+                None => break,
+                Some(item) => out.push(item),
+            }
+            popped += 1;
+        }
+
+        popped
+    }
+
+    // Moves at most `max` items from self into dst, returns how many were moved.
+    // If dst is full, the item already popped is pushed back to the tail of self,
+    //   so it may be reordered against the items still left in self.
+    fn transfer<D: Queue<Item = Self::Item>>(&self, dst: &D, max: usize) -> usize {
+        let mut moved = 0;
+
+        while moved < max {
+            let mut item = match self.pop() {
+                None => break,
+                Some(item) => item,
+            };
+
+            // Someone may fill up self or drain dst concurrently, so retry both until one of them accepts
+            loop {
+                item = match dst.push(item) {
+                    Ok(()) => break,
+                    Err(item) => item,
+                };
+
+                item = match self.push(item) {
+                    Ok(()) => return moved,
+                    Err(item) => item,
+                };
+            }
+
+            moved += 1;
+        }
+
+        moved
+    }
+
+    // None if the cap set through Handles::set_max is reached
+    fn producer(&self) -> Option<Producer<'_, Self>> where Self: Sized {
+        if !self.handles().acquire(Handle::Producer) {
+            return None;
+        }
+
+        Some(Producer {
+            queue: self,
+        })
+    }
+
+    fn consumer(&self) -> Option<Consumer<'_, Self>> where Self: Sized {
+        if !self.handles().acquire(Handle::Consumer) {
+            return None;
+        }
+
+        Some(Consumer {
+            queue: self,
+        })
+    }
+
+    // Handles owning a reference to the queue, so they can be moved anywhere without a &'static.
+    // The queue goes away with its last Arc, handles included.
+    #[cfg(any(feature = "alloc", test))]
+    fn arc_producer(self: Arc<Self>) -> Option<ArcProducer<Self>> where Self: Sized {
+        if !self.handles().acquire(Handle::Producer) {
+            return None;
+        }
+
+        Some(ArcProducer {
+            queue: self,
+        })
+    }
+
+    #[cfg(any(feature = "alloc", test))]
+    fn arc_consumer(self: Arc<Self>) -> Option<ArcConsumer<Self>> where Self: Sized {
+        if !self.handles().acquire(Handle::Consumer) {
+            return None;
+        }
+
+        Some(ArcConsumer {
+            queue: self,
+        })
+    }
+}
+
+// Where the slots of a TicketQueue live. Either way the capacity is fixed once the queue is built.
+pub trait SlotStorage: Send + Sync {
+    type Item;
+    type Sequencer: Sequencer;
+
+    // Shown by Debug
+    const NAME: &'static str;
+
+    fn slots(&self) -> &[Slot<Self::Item, Self::Sequencer>];
+}
+
+impl<T, S: Sequencer, const N: usize> SlotStorage for [Slot<T, S>; {N}] {
+    type Item = T;
+    type Sequencer = S;
+
+    const NAME: &'static str = "StaticQueue";
+
+    fn slots(&self) -> &[Slot<T, S>] {
+        self
+    }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<T, S: Sequencer> SlotStorage for alloc::boxed::Box<[Slot<T, S>]> {
+    type Item = T;
+    type Sequencer = S;
+
+    const NAME: &'static str = "HeapQueue";
+
+    fn slots(&self) -> &[Slot<T, S>] {
+        self
+    }
+}
+
+// A pop ticket whose push didn't land before pop_timeout gave up. The slot stays reserved for it,
+//   so the item can still be collected with resume. Dropping it leaves the slot stuck,
+//   along with everything that would go through it after.
+// It borrows the queue the ticket was taken from, so it can't be resumed on any other.
+#[must_use]
+pub struct PendingPop<'a, B: SlotStorage, K: Backoff> {
+    queue: &'a TicketQueue<B, K>,
+    ticket: usize,
+}
+
+impl<'a, B: SlotStorage, K: Backoff> PendingPop<'a, B, K> where B::Sequencer: TimeoutSequencer {
+    // Wait for the push once more, at most timeout. Hands the ticket back if it still hasn't landed.
+    pub fn resume(self, timeout: core::time::Duration) -> Result<B::Item, Self> {
+        let (slot, next) = self.queue.slot(self.ticket);
+        slot.pop_timeout(self.ticket, next, timeout).ok_or(self)
+    }
+}
+
+// The same ticket of the same queue
+impl<'a, B: SlotStorage, K: Backoff> PartialEq for PendingPop<'a, B, K> {
+    fn eq(&self, other: &Self) -> bool {
+        core::ptr::eq(self.queue, other.queue) && self.ticket == other.ticket
+    }
+}
+
+impl<'a, B: SlotStorage, K: Backoff> Eq for PendingPop<'a, B, K> {}
+
+impl<'a, B: SlotStorage, K: Backoff> core::fmt::Debug for PendingPop<'a, B, K> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PendingPop").field("ticket", &self.ticket).finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushErrorKind {
+    // No free slot right now, consumers may make room
+    Full,
+    // Refused for good
+    Closed,
+}
+
+// A refused push, with the item handed back and a snapshot of the queue at the time,
+//   so producers can choose between spinning, backing off and giving up
+#[derive(Debug, PartialEq, Eq)]
+pub struct PushError<T> {
+    pub item: T,
+    pub kind: PushErrorKind,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl<T> PushError<T> {
+    pub fn into_inner(self) -> T {
+        self.item
+    }
+}
+
+// Totals since the queue was built, see TicketQueue::stats
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QueueStats {
+    pub pushes: u64,
+    // Refused because the queue was full
+    pub full_pushes: u64,
+    pub pops: u64,
+    // Found nothing to pop
+    pub empty_pops: u64,
+}
+
+#[cfg(feature = "stats")]
+struct Counters {
+    pushes: AtomicU64,
+    full_pushes: AtomicU64,
+    pops: AtomicU64,
+    empty_pops: AtomicU64,
+}
+
+#[cfg(feature = "stats")]
+impl Counters {
+    fn new() -> Self {
+        Self {
+            pushes: AtomicU64::new(0),
+            full_pushes: AtomicU64::new(0),
+            pops: AtomicU64::new(0),
+            empty_pops: AtomicU64::new(0),
+        }
+    }
+
+    // Relaxed, the counters don't order anything and a snapshot is allowed to be slightly stale
+    fn add(counter: &AtomicU64, n: usize) {
+        counter.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+// The lock-free ring, over either storage. Use it through StaticQueue or HeapQueue.
+// K decides what the ticket CAS loops do after losing a race.
+pub struct TicketQueue<B: SlotStorage, K: Backoff = NoBackoff> {
+    slots: B,
+
+    // The top bit is the closed flag, see CLOSED
+    push_ticket: AtomicUsize,
+    pop_ticket: AtomicUsize,
+
+    handles: Handles,
+
+    backoff: core::marker::PhantomData<fn() -> K>,
+
+    // Compiled out entirely without the stats feature, so counting costs nothing unless asked for
+    #[cfg(feature = "stats")]
+    counters: Counters,
+}
+
+impl<B: SlotStorage, K: Backoff> TicketQueue<B, K> {
+    fn new(slots: B) -> Self {
+        // Slot i first serves ticket i, the sequencers start out at 0
+        for (ticket, slot) in slots.slots().iter().enumerate() {
+            unsafe { slot.reset(ticket) };
+        }
+
+        Self {
+            slots,
+            push_ticket: AtomicUsize::new(0),
+            pop_ticket: AtomicUsize::new(0),
+            handles: Handles::new(),
+            backoff: core::marker::PhantomData,
+            #[cfg(feature = "stats")]
+            counters: Counters::new(),
+        }
+    }
+
+    // Array length for StaticQueue, which folds into a constant once inlined
+    fn cap(&self) -> usize {
+        self.slots.slots().len()
+    }
+
+    // The slot serving ticket, and the ticket it serves after that.
+    // Power of two capacities take a mask instead of a division. For StaticQueue the check folds away,
+    //   for HeapQueue it's a well predicted branch.
+    fn slot(&self, ticket: usize) -> (&Slot<B::Item, B::Sequencer>, usize) {
+        let cap = self.cap();
+        let idx = if cap.is_power_of_two() {
+            ticket & (cap - 1)
+        } else {
+            ticket % cap
+        };
+
+        (&self.slots.slots()[idx], Self::advance(ticket, cap))
+    }
+
+    // Closing sets the top bit of push_ticket, so it's ordered against taking push tickets by that one word:
+    //   a push CAS expects the bit clear and fails once it's set. No push can land after a consumer saw the queue closed.
+    const CLOSED: usize = !(usize::MAX >> 1);
+    const TICKETS: usize = usize::MAX >> 1;
+
+    // Tickets only ever grow, wrapping around below the closed bit. Compared through their wrapping distance,
+    //   which stays right as long as they are less than 2^(usize::BITS - 2) apart, and they are at most a capacity apart.
+    // A capacity that isn't a power of two doesn't divide 2^(usize::BITS - 1) though, so its ticket-to-slot mapping jumps
+    //   once the tickets wrap. That takes 2^63 operations, out of reach, but only 2^31 on 32-bit targets,
+    //   so those only take power of two capacities, see NARROW_WRAPS.
+    fn distance(from: usize, to: usize) -> isize {
+        (to.wrapping_sub(from) << 1) as isize >> 1
+    }
+
+    fn advance(ticket: usize, n: usize) -> usize {
+        ticket.wrapping_add(n) & Self::TICKETS
+    }
+
+    // push_ticket without the closed bit
+    fn load_push_ticket(&self) -> usize {
+        self.push_ticket.load(Ordering::Acquire) & Self::TICKETS
+    }
+
+    // On failure, why, along with the length seen
+    fn obtain_push_ticket(&self) -> Result<usize, (PushErrorKind, usize)> {
+        let mut backoff = K::default();
+        loop {
+            let cur_push = self.push_ticket.load(Ordering::Acquire);
+            let cur_pop = self.pop_ticket.load(Ordering::Acquire);
+
+            let size = Self::distance(cur_pop, cur_push & Self::TICKETS);
+            let len = core::cmp::min(core::cmp::max(size, 0) as usize, self.cap());
+
+            // Closed first, a closed queue that's also full is never taking anything again
+            if cur_push & Self::CLOSED != 0 {
+                break Err((PushErrorKind::Closed, len));
+            }
+
+            // Queue is full
+            if size >= self.cap() as isize {
+                #[cfg(feature = "stats")]
+                Counters::add(&self.counters.full_pushes, 1);
+                break Err((PushErrorKind::Full, len));
+            }
+
+            // TODO: do we need Release here?
+            if self.push_ticket.compare_and_swap(cur_push, Self::advance(cur_push, 1), Ordering::AcqRel) == cur_push {
+                #[cfg(feature = "stats")]
+                Counters::add(&self.counters.pushes, 1);
+                break Ok(cur_push);
+            }
+
+            backoff.backoff();
+        }
+    }
+
+    // Reserves a run of at most max consecutive push tickets with a single CAS.
+    // The run is cut at the free space seen in the snapshot. Free space only grows while push_ticket stays put,
+    //   so a successful CAS never reserves past capacity. Returns the first ticket and the length of the run.
+    #[cfg(any(feature = "alloc", test))]
+    fn obtain_push_tickets(&self, max: usize) -> Option<(usize, usize)> {
+        let mut backoff = K::default();
+        loop {
+            let cur_push = self.push_ticket.load(Ordering::Acquire);
+            let cur_pop = self.pop_ticket.load(Ordering::Acquire);
+
+            if max == 0 || cur_push & Self::CLOSED != 0 {
+                break None;
+            }
+
+            let size = Self::distance(cur_pop, cur_push);
+
+            if size >= self.cap() as isize {
+                #[cfg(feature = "stats")]
+                Counters::add(&self.counters.full_pushes, 1);
+                break None;
+            }
+
+            let cnt = core::cmp::min(max, (self.cap() as isize - size) as usize);
+            if self.push_ticket.compare_and_swap(cur_push, Self::advance(cur_push, cnt), Ordering::AcqRel) == cur_push {
+                #[cfg(feature = "stats")]
+                Counters::add(&self.counters.pushes, cnt);
+                break Some((cur_push, cnt));
+            }
+
+            backoff.backoff();
+        }
+    }
+
+    #[cfg(any(feature = "alloc", test))]
+    fn obtain_pop_tickets(&self, max: usize) -> Option<(usize, usize)> {
+        let mut backoff = K::default();
+        let mut retries = 0;
+        loop {
+            let cur_pop = self.pop_ticket.load(Ordering::Acquire);
+            let cur_push = self.load_push_ticket();
+
+            if max == 0 {
+                return None;
+            }
+
+            let size = Self::distance(cur_pop, cur_push);
+            if size <= 0 {
+                if self.retry_empty(cur_pop, cur_push, &mut retries) {
+                    backoff.backoff();
+                    continue;
+                }
+
+                #[cfg(feature = "stats")]
+                Counters::add(&self.counters.empty_pops, 1);
+                return None;
+            }
+
+            let cnt = core::cmp::min(max, size as usize);
+            if self.pop_ticket.compare_and_swap(cur_pop, Self::advance(cur_pop, cnt), Ordering::AcqRel) == cur_pop {
+                #[cfg(feature = "stats")]
+                Counters::add(&self.counters.pops, cnt);
+                break Some((cur_pop, cnt));
+            }
+
+            backoff.backoff();
+        }
+    }
+
+    // (occupied, sequence) of every slot, in storage order, for debugging a stuck queue. Nothing is modified.
+    // An even sequence 2 * t means the slot waits for the push of ticket t, an odd one 2 * t + 1 that it holds ticket t's item.
+    // A slot occupied but still even has its push in progress. One that stays even while push_ticket is past t
+    //   belongs to a producer that took its ticket and never got to write.
+    // Only a snapshot, slots may change while it's taken.
+    #[cfg(any(feature = "alloc", test))]
+    pub fn occupancy_snapshot(&self) -> Vec<(bool, usize)> {
+        self.slots.slots().iter().map(Slot::snapshot).collect()
+    }
+
+    // Salvage slots out of sync with the tickets, e.g. after a push or pop was cut off between its ticket and its slot.
+    // Empties the queue, forgetting the items inside without dropping them since their slots can't be trusted.
+    // Safety: nothing else may touch the queue during the call, and no push or pop may be left pending.
+    pub unsafe fn reinit_slots(&self) {
+        let cur_push = self.load_push_ticket();
+        self.pop_ticket.store(cur_push, Ordering::Release);
+
+        for i in 0..self.cap() {
+            let ticket = Self::advance(cur_push, i);
+            let (slot, _) = self.slot(ticket);
+            slot.reset(ticket);
+        }
+    }
+
+    // Drops everything still queued and rewinds both tickets to zero, as if freshly built.
+    // &mut self rules out pushes and pops in flight, so every slot is where its ticket says it is.
+    // Closing and the handle cap are left as they are.
+    pub fn clear(&mut self) {
+        while self.pop().is_some() {}
+
+        for (ticket, slot) in self.slots.slots().iter().enumerate() {
+            unsafe { slot.reset(ticket) };
+        }
+        let closed = self.push_ticket.load(Ordering::Acquire) & Self::CLOSED;
+        self.push_ticket.store(closed, Ordering::Release);
+        self.pop_ticket.store(0, Ordering::Release);
+    }
+
+    // push, telling a full queue apart from a closed one
+    pub fn try_push_detailed(&self, t: B::Item) -> Result<(), PushError<B::Item>> {
+        let ticket = match self.obtain_push_ticket() {
+            Err((kind, len)) => return Err(PushError { item: t, kind, len, capacity: self.cap() }),
+            Ok(ticket) => ticket,
+        };
+
+        let (slot, _) = self.slot(ticket);
+
+        slot.push(t, ticket);
+
+        Ok(())
+    }
+
+    // Ring buffer push: when full, evict and drop the oldest item to make room instead of failing.
+    // The eviction takes a pop ticket like any consumer would, so it can't read a slot a consumer also reads.
+    // With other producers around, room freed here may be taken by them, and the loop evicts again.
+    // Only fails once the queue is closed.
+    pub fn overwrite_push(&self, t: B::Item) -> Result<(), B::Item> {
+        let mut t = t;
+        loop {
+            t = match self.push(t) {
+                Ok(()) => return Ok(()),
+                Err(t) => t,
+            };
+
+            if self.is_closed() {
+                return Err(t);
+            }
+
+            // Consumers may have made room already, in which case there's nothing to evict
+            if let Some(ticket) = self.obtain_pop_ticket() {
+                let (slot, next) = self.slot(ticket);
+                drop(slot.pop(ticket, next));
+            }
+        }
+    }
+
+    // Evictions by overwrite_push count as pops
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            pushes: self.counters.pushes.load(Ordering::Relaxed),
+            full_pushes: self.counters.full_pushes.load(Ordering::Relaxed),
+            pops: self.counters.pops.load(Ordering::Relaxed),
+            empty_pops: self.counters.empty_pops.load(Ordering::Relaxed),
+        }
+    }
+
+    // How many times an empty looking snapshot is taken again before a pop gives up
+    const EMPTY_RETRIES: usize = 4;
+
+    // Whether a pop seeing (cur_pop, cur_push) as empty should take another snapshot instead of returning None.
+    // A negative distance means the loads raced with other threads moving the tickets, and by the time
+    //   an empty snapshot is looked at, a push may have landed. Without a second look, a consumer losing
+    //   those races again and again keeps returning None while others pop the items it could have had.
+    // Bounded, so a genuinely empty queue still returns right away: one fresh load of push_ticket that hasn't moved.
+    // Only the snapshot is retried, tickets are still claimed by the CAS alone, so no two consumers get the same one.
+    fn retry_empty(&self, cur_pop: usize, cur_push: usize, retries: &mut usize) -> bool {
+        if *retries >= Self::EMPTY_RETRIES {
+            return false;
+        }
+        *retries += 1;
+
+        if Self::distance(cur_pop, cur_push) < 0 {
+            return true;
+        }
+
+        fence(Ordering::Acquire);
+        self.load_push_ticket() != cur_push
+    }
+
+    fn obtain_pop_ticket(&self) -> Option<usize> {
+        let mut backoff = K::default();
+        let mut retries = 0;
+        loop {
+            let cur_pop = self.pop_ticket.load(Ordering::Acquire);
+            let cur_push = self.load_push_ticket();
+
+            if Self::distance(cur_pop, cur_push) <= 0 {
+                if self.retry_empty(cur_pop, cur_push, &mut retries) {
+                    backoff.backoff();
+                    continue;
+                }
+
+                #[cfg(feature = "stats")]
+                Counters::add(&self.counters.empty_pops, 1);
+                return None;
+            }
+
+            // TODO: do we need Release here?
+            if self.pop_ticket.compare_and_swap(cur_pop, Self::advance(cur_pop, 1), Ordering::AcqRel) == cur_pop {
+                #[cfg(feature = "stats")]
+                Counters::add(&self.counters.pops, 1);
+                break Some(cur_pop);
+            }
+
+            backoff.backoff();
+        }
+    }
+}
+
+// Only for sequencers that can give up waiting, SpinSequencer can't
+impl<B: SlotStorage, K: Backoff> TicketQueue<B, K> where B::Sequencer: TimeoutSequencer {
+    // Pop, but wait at most timeout for the push behind the ticket to land, e.g. if its producer stalled or died
+    //   between taking the ticket and writing. Ok(None) if the queue is empty.
+    pub fn pop_timeout(&self, timeout: core::time::Duration) -> Result<Option<B::Item>, PendingPop<'_, B, K>> {
+        let ticket = match self.obtain_pop_ticket() {
+            None => return Ok(None),
+            Some(ticket) => ticket,
+        };
+
+        PendingPop { queue: self, ticket }.resume(timeout).map(Some)
+    }
+}
+
+impl<B: SlotStorage, K: Backoff> Queue for TicketQueue<B, K> {
+    type Item = B::Item;
+
+    fn pop(&self) -> Option<Self::Item> {
+        let ticket = self.obtain_pop_ticket()?;
+
+        let (slot, next) = self.slot(ticket);
+        Some(slot.pop(ticket, next))
+    }
+
+    fn push(&self, t: Self::Item) -> Result<(), Self::Item> {
+        self.try_push_detailed(t).map_err(PushError::into_inner)
+    }
+
+    // With no pop racing, pop_ticket stays put, and a push has claimed it if it's behind push_ticket.
+    // That push may still be writing, so wait on the slot the same way pop would.
+    unsafe fn shared_peek(&self) -> Option<&Self::Item> {
+        let cur_pop = self.pop_ticket.load(Ordering::Acquire);
+        let cur_push = self.load_push_ticket();
+
+        if Self::distance(cur_pop, cur_push) <= 0 {
+            return None;
+        }
+
+        let (slot, _) = self.slot(cur_pop);
+        Some(slot.peek(cur_pop))
+    }
+
+    fn push_notify<F: FnOnce()>(&self, t: Self::Item, on_became_nonempty: F) -> Result<(), Self::Item> {
+        let ticket = match self.obtain_push_ticket() {
+            Err(_) => return Err(t),
+            Ok(ticket) => ticket,
+        };
+
+        // pop_ticket can't pass our ticket before we moved push_ticket past it.
+        // If it has caught up with our ticket now, the queue was empty right before this push,
+        //   or it has been drained down to our item since, in which case the call is redundant but harmless.
+        // The fence keeps a consumer that just saw the queue empty from being missed here.
+        fence(Ordering::SeqCst);
+        let became_nonempty = Self::distance(ticket, self.pop_ticket.load(Ordering::Acquire)) >= 0;
+
+        let (slot, _) = self.slot(ticket);
+
+        slot.push(t, ticket);
+
+        // Only after the item is in place, so whoever gets woken up can take it
+        if became_nonempty {
+            on_became_nonempty();
+        }
+
+        Ok(())
+    }
+
+    // Every ticket in the run still goes through its own slot's sequencer,
+    //   so single pushes and pops racing with the batch are ordered just like before.
+    #[cfg(any(feature = "alloc", test))]
+    fn push_slice(&self, items: &mut Vec<Self::Item>) -> usize {
+        let (first, cnt) = match self.obtain_push_tickets(items.len()) {
+            None => return 0,
+            Some(run) => run,
+        };
+
+        for (i, t) in items.drain(..cnt).enumerate() {
+            let ticket = Self::advance(first, i);
+            let (slot, _) = self.slot(ticket);
+            slot.push(t, ticket);
+        }
+
+        cnt
+    }
+
+    #[cfg(any(feature = "alloc", test))]
+    fn pop_batch(&self, out: &mut Vec<Self::Item>, max: usize) -> usize {
+        let (first, cnt) = match self.obtain_pop_tickets(max) {
+            None => return 0,
+            Some(run) => run,
+        };
+
+        out.reserve(cnt);
+        for i in 0..cnt {
+            let ticket = Self::advance(first, i);
+            let (slot, next) = self.slot(ticket);
+            out.push(slot.pop(ticket, next));
+        }
+
+        cnt
+    }
+
+    fn len(&self) -> usize {
+        // pop_ticket never passes push_ticket, so reading it first keeps the difference non-negative.
+        // Clamp anyway, and also to the capacity in case some pops happened between the two loads.
+        let cur_pop = self.pop_ticket.load(Ordering::Acquire);
+        let cur_push = self.load_push_ticket();
+
+        core::cmp::min(core::cmp::max(Self::distance(cur_pop, cur_push), 0) as usize, self.cap())
+    }
+
+    fn capacity(&self) -> usize {
+        self.cap()
+    }
+
+    fn close(&self) {
+        self.push_ticket.fetch_or(Self::CLOSED, Ordering::AcqRel);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.push_ticket.load(Ordering::Acquire) & Self::CLOSED != 0
+    }
+
+    fn handles(&self) -> &Handles {
+        &self.handles
+    }
+}
+
+// Built field by field, an all-zero sequencer isn't necessarily a valid one (e.g. one holding a Mutex)
+// Whether a capacity keeps its ticket-to-slot mapping across the wraparound of the tickets on this target, see distance
+const fn wraps_evenly(cap: usize) -> bool {
+    core::mem::size_of::<usize>() > 4 || cap.is_power_of_two()
+}
+
+impl<T, S: Sequencer, K: Backoff, const N: usize> TicketQueue<[Slot<T, S>; {N}], K> {
+    // Fails the build when a capacity that breaks across the wraparound is instantiated on a 32-bit target
+    const NARROW_WRAPS: () = assert!(wraps_evenly(N), "On targets with 32-bit usize, the capacity has to be a power of two");
+}
+
+impl<T, S: Sequencer, K: Backoff, const N: usize> Default for TicketQueue<[Slot<T, S>; {N}], K> {
+    fn default() -> Self {
+        let _ = Self::NARROW_WRAPS;
+
+        Self::new(init_array(Default::default))
+    }
+}
+
+// Slots allocated once, for a capacity only known at runtime
+#[cfg(any(feature = "alloc", test))]
+impl<T, S: Sequencer, K: Backoff> TicketQueue<alloc::boxed::Box<[Slot<T, S>]>, K> {
+    pub fn with_capacity(n: usize) -> Self {
+        assert!(n > 0, "HeapQueue needs a capacity of at least 1");
+        assert!(wraps_evenly(n), "On targets with 32-bit usize, the capacity has to be a power of two");
+        let slots: Vec<Slot<T, S>> = (0..n).map(|_| Default::default()).collect();
+        Self::new(slots.into_boxed_slice())
+    }
+}
+
+// Only bookkeeping is shown. Slots may hold uninitialized memory, and T needn't be Debug anyway.
+impl<B: SlotStorage, K: Backoff> core::fmt::Debug for TicketQueue<B, K> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct(B::NAME)
+            .field("push_ticket", &(self.push_ticket.load(Ordering::Relaxed) & Self::TICKETS))
+            .field("pop_ticket", &self.pop_ticket.load(Ordering::Relaxed))
+            .field("len", &self.len())
+            .field("capacity", &self.cap())
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+pub type StaticQueue<T, S, const N: usize> = TicketQueue<[Slot<T, S>; {N}]>;
+// Const parameters have to come last, so the backoff can't be a defaulted parameter of StaticQueue
+pub type StaticBackoffQueue<T, S, K, const N: usize> = TicketQueue<[Slot<T, S>; {N}], K>;
+#[cfg(any(feature = "alloc", test))]
+pub type HeapQueue<T, S, K = NoBackoff> = TicketQueue<alloc::boxed::Box<[Slot<T, S>]>, K>;
+
+pub struct Consumer<'a, Q: Queue> {
+    queue: &'a Q,
+}
+
+pub struct Producer<'a, Q: Queue> {
+    queue: &'a Q,
+}
+
+// A copy is one more live handle, so it's None past the cap like consumer() and producer(). That's why there's no Clone.
+impl<'a, Q: Queue> Consumer<'a, Q> {
+    pub fn try_clone(&self) -> Option<Self> {
+        if !self.queue.handles().acquire(Handle::Consumer) {
+            return None;
+        }
+        Some(Self { queue: self.queue })
+    }
+}
+
+impl<'a, Q: Queue> Producer<'a, Q> {
+    pub fn try_clone(&self) -> Option<Self> {
+        if !self.queue.handles().acquire(Handle::Producer) {
+            return None;
+        }
+        Some(Self { queue: self.queue })
+    }
+}
+
+impl<'a, Q: Queue> core::fmt::Debug for Consumer<'a, Q> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Consumer").field("queue", &format_args!("{}", core::any::type_name::<Q>())).finish()
+    }
+}
+
+impl<'a, Q: Queue> core::fmt::Debug for Producer<'a, Q> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Producer").field("queue", &format_args!("{}", core::any::type_name::<Q>())).finish()
+    }
+}
+
+impl<'a, Q: Queue> Drop for Consumer<'a, Q> {
+    fn drop(&mut self) {
+        self.queue.handles().release(Handle::Consumer);
+    }
+}
+
+impl<'a, Q: Queue> Drop for Producer<'a, Q> {
+    fn drop(&mut self) {
+        self.queue.handles().release(Handle::Producer);
+    }
+}
+
+impl<'a, Q: Queue> Consumer<'a, Q> {
+    pub fn pop(&self) -> Option<Q::Item> {
+        self.queue.pop()
+    }
+
+    pub fn try_pop(&self) -> Pop<Q::Item> {
+        self.queue.try_pop()
+    }
+
+    #[cfg(any(feature = "alloc", test))]
+    pub fn pop_batch(&self, out: &mut Vec<Q::Item>, max: usize) -> usize {
+        self.queue.pop_batch(out, max)
+    }
+
+    pub fn queue(&self) -> &'a Q {
+        self.queue
+    }
+}
+
+// Pops until the first None. The queue may only be empty for the moment, so a new iterator
+//   on the same consumer can pick up items pushed later.
+impl<'a, Q: Queue> Iterator for Consumer<'a, Q> {
+    type Item = Q::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.queue.pop()
+    }
+}
+
+impl<'a, Q: Queue> Consumer<'a, Q> {
+    // Takes out every item currently queued that matches f, and puts the others back at the tail, in order.
+    // Only keeps the order if this is the sole consumer and producers are quiet during the call,
+    //   anything pushed meanwhile ends up in front of the items put back.
+    // Items that can't be put back, because the queue got closed or a producer took the freed slot,
+    //   are appended to the result after the matching ones instead of being lost.
+    #[cfg(any(feature = "alloc", test))]
+    pub fn drain_filter<F: FnMut(&Q::Item) -> bool>(&mut self, mut f: F) -> Vec<Q::Item> {
+        let mut matched = Vec::new();
+        let mut stranded = Vec::new();
+
+        for item in self.drain() {
+            if f(&item) {
+                matched.push(item);
+            } else if let Err(item) = self.queue.push(item) {
+                stranded.push(item);
+            }
+        }
+
+        matched.append(&mut stranded);
+        matched
+    }
+
+    // Pops at most as many items as the queue held when this was called,
+    //   so it doesn't chase producers that keep pushing. Stops early if other consumers got there first.
+    pub fn drain(&self) -> Drain<'a, Q> {
+        Drain {
+            queue: self.queue,
+            left: self.queue.len(),
+        }
+    }
+}
+
+pub struct Drain<'a, Q: Queue> {
+    queue: &'a Q,
+    left: usize,
+}
+
+impl<'a, Q: Queue> Iterator for Drain<'a, Q> {
+    type Item = Q::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.left == 0 {
+            return None;
+        }
+
+        self.left -= 1;
+        let result = self.queue.pop();
+        if result.is_none() {
+            self.left = 0;
+        }
+        result
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.left))
+    }
+}
+
+// The only consumer of its queue, which makes peek sound: the item it points to can only go away
+//   through pop, and pop needs the consumer borrowed mutably. Any number of producers is fine.
+// It isn't counted as a consumer handle, nothing else may pop anyway.
+pub struct SpscConsumer<'a, Q: Queue> {
+    queue: &'a Q,
+}
+
+impl<'a, Q: Queue> core::fmt::Debug for SpscConsumer<'a, Q> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SpscConsumer").field("queue", &format_args!("{}", core::any::type_name::<Q>())).finish()
+    }
+}
+
+impl<'a, Q: Queue> SpscConsumer<'a, Q> {
+    // Safety: nothing else may pop while this consumer lives, including other consumers,
+    //   pop on the queue itself, and whatever is built on it, like transfer.
+    pub unsafe fn new(queue: &'a Q) -> Self {
+        Self { queue }
+    }
+
+    // None if the queue is empty, or if it doesn't override Queue::shared_peek
+    pub fn peek(&self) -> Option<&Q::Item> {
+        unsafe { self.queue.shared_peek() }
+    }
+
+    pub fn pop(&mut self) -> Option<Q::Item> {
+        self.queue.pop()
+    }
+
+    pub fn try_pop(&mut self) -> Pop<Q::Item> {
+        self.queue.try_pop()
+    }
+
+    pub fn queue(&self) -> &'a Q {
+        self.queue
+    }
+}
+
+impl<'a, Q: Queue> Producer<'a, Q> {
+    pub fn push(&self, data: Q::Item) -> Result<(), Q::Item> {
+        self.queue.push(data)
+    }
+
+    pub fn push_notify<F: FnOnce()>(&self, data: Q::Item, on_became_nonempty: F) -> Result<(), Q::Item> {
+        self.queue.push_notify(data, on_became_nonempty)
+    }
+
+    #[cfg(any(feature = "alloc", test))]
+    pub fn push_slice(&self, items: &mut Vec<Q::Item>) -> usize {
+        self.queue.push_slice(items)
+    }
+
+    pub fn queue(&self) -> &'a Q {
+        self.queue
+    }
+}
+
+impl<'a, B: SlotStorage, K: Backoff> Consumer<'a, TicketQueue<B, K>> where B::Sequencer: TimeoutSequencer {
+    pub fn pop_timeout(&self, timeout: core::time::Duration) -> Result<Option<B::Item>, PendingPop<'a, B, K>> {
+        self.queue.pop_timeout(timeout)
+    }
+}
+
+impl<'a, B: SlotStorage, K: Backoff> Producer<'a, TicketQueue<B, K>> {
+    pub fn try_push_detailed(&self, data: B::Item) -> Result<(), PushError<B::Item>> {
+        self.queue.try_push_detailed(data)
+    }
+
+    pub fn overwrite_push(&self, data: B::Item) -> Result<(), B::Item> {
+        self.queue.overwrite_push(data)
+    }
+}
+
+// Same as Producer and Consumer, holding an Arc instead of a borrow. Counted against the same cap.
+#[cfg(any(feature = "alloc", test))]
+pub struct ArcProducer<Q: Queue> {
+    queue: Arc<Q>,
+}
+
+#[cfg(any(feature = "alloc", test))]
+pub struct ArcConsumer<Q: Queue> {
+    queue: Arc<Q>,
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<Q: Queue> ArcProducer<Q> {
+    pub fn try_clone(&self) -> Option<Self> {
+        if !self.queue.handles().acquire(Handle::Producer) {
+            return None;
+        }
+        Some(Self { queue: self.queue.clone() })
+    }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<Q: Queue> ArcConsumer<Q> {
+    pub fn try_clone(&self) -> Option<Self> {
+        if !self.queue.handles().acquire(Handle::Consumer) {
+            return None;
+        }
+        Some(Self { queue: self.queue.clone() })
+    }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<Q: Queue> core::fmt::Debug for ArcProducer<Q> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ArcProducer").field("queue", &format_args!("{}", core::any::type_name::<Q>())).finish()
+    }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<Q: Queue> core::fmt::Debug for ArcConsumer<Q> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ArcConsumer").field("queue", &format_args!("{}", core::any::type_name::<Q>())).finish()
+    }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<Q: Queue> Drop for ArcProducer<Q> {
+    fn drop(&mut self) {
+        self.queue.handles().release(Handle::Producer);
+    }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<Q: Queue> Drop for ArcConsumer<Q> {
+    fn drop(&mut self) {
+        self.queue.handles().release(Handle::Consumer);
+    }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<Q: Queue> ArcProducer<Q> {
+    pub fn push(&self, data: Q::Item) -> Result<(), Q::Item> {
+        self.queue.push(data)
+    }
+
+    pub fn push_notify<F: FnOnce()>(&self, data: Q::Item, on_became_nonempty: F) -> Result<(), Q::Item> {
+        self.queue.push_notify(data, on_became_nonempty)
+    }
+
+    pub fn push_slice(&self, items: &mut Vec<Q::Item>) -> usize {
+        self.queue.push_slice(items)
+    }
+
+    pub fn queue(&self) -> &Arc<Q> {
+        &self.queue
+    }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<Q: Queue> ArcConsumer<Q> {
+    pub fn pop(&self) -> Option<Q::Item> {
+        self.queue.pop()
+    }
+
+    pub fn try_pop(&self) -> Pop<Q::Item> {
+        self.queue.try_pop()
+    }
+
+    pub fn pop_batch(&self, out: &mut Vec<Q::Item>, max: usize) -> usize {
+        self.queue.pop_batch(out, max)
+    }
+
+    pub fn queue(&self) -> &Arc<Q> {
+        &self.queue
+    }
+}
+
+// Pops until the first None, like Consumer
+#[cfg(any(feature = "alloc", test))]
+impl<Q: Queue> Iterator for ArcConsumer<Q> {
+    type Item = Q::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.queue.pop()
+    }
+}
+
+pub type StaticSpinQueue<T, const N: usize> = StaticQueue<T, super::sequencer::SpinSequencer, {N}>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let queue: StaticSpinQueue<usize, 4> = Default::default();
+
+        let mut producer = queue.producer().unwrap();
+        let mut consumer = queue.consumer().unwrap();
+
+        assert_eq!(queue.capacity(), 4);
+        assert_eq!(queue.len(), 0);
+        assert!(queue.is_empty());
+
+        producer.push(1).unwrap();
+        producer.push(2).unwrap();
+        assert_eq!(queue.len(), 2);
+        assert!(!queue.is_empty());
+        producer.push(3).unwrap();
+        producer.push(4).unwrap();
+        let data = producer.push(5).unwrap_err();
+        assert_eq!(data, 5);
+        assert_eq!(queue.len(), 4);
+
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), Some(3));
+        assert_eq!(queue.len(), 1);
+
+        producer.push(5).unwrap();
+        producer.push(6).unwrap();
+        producer.push(7).unwrap();
+        let data = producer.push(8).unwrap_err();
+        assert_eq!(data, 8);
+
+        assert_eq!(consumer.pop(), Some(4));
+        assert_eq!(consumer.pop(), Some(5));
+        assert_eq!(consumer.pop(), Some(6));
+        assert_eq!(consumer.pop(), Some(7));
+        assert_eq!(queue.len(), 0);
+        assert!(queue.is_empty());
+        assert_eq!(consumer.pop(), None);
+        assert_eq!(consumer.pop(), None);
+        assert_eq!(consumer.pop(), None);
+
+        producer.push(8).unwrap();
+        assert_eq!(consumer.pop(), Some(8));
+
+        producer.push(9).unwrap();
+        assert_eq!(consumer.try_pop(), Pop::Item(9));
+        assert_eq!(consumer.try_pop(), Pop::Empty);
+
+        producer.push(10).unwrap();
+        queue.close();
+        assert!(queue.is_closed());
+        assert_eq!(producer.push(11), Err(11));
+        assert_eq!(consumer.try_pop(), Pop::Item(10));
+        assert_eq!(consumer.try_pop(), Pop::Closed);
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn clear() {
+        use std::rc::Rc;
+
+        struct Counted(Rc<()>);
+
+        let alive = Rc::new(());
+        let mut queue: StaticSpinQueue<Counted, 4> = Default::default();
+
+        // Leave the tickets somewhere in the middle of the ring
+        for _ in 0..3 {
+            queue.push(Counted(alive.clone())).ok().unwrap();
+        }
+        queue.pop().unwrap();
+        queue.push(Counted(alive.clone())).ok().unwrap();
+        assert_eq!(Rc::strong_count(&alive), 4);
+
+        queue.clear();
+        assert_eq!(Rc::strong_count(&alive), 1);
+        assert!(queue.is_empty());
+        assert!(queue.pop().is_none());
+        assert_eq!(queue.push_ticket.load(Ordering::Acquire), 0);
+        assert_eq!(queue.pop_ticket.load(Ordering::Acquire), 0);
+
+        for _ in 0..4 {
+            queue.push(Counted(alive.clone())).ok().unwrap();
+        }
+        assert!(queue.push(Counted(alive.clone())).is_err());
+        queue.clear();
+        assert_eq!(Rc::strong_count(&alive), 1);
+    }
+
+    #[test]
+    fn occupancy_snapshot() {
+        let queue: StaticSpinQueue<usize, 4> = Default::default();
+        assert_eq!(queue.occupancy_snapshot(), vec![(false, 0), (false, 2), (false, 4), (false, 6)]);
+
+        queue.push(0).unwrap();
+        assert_eq!(queue.pop(), Some(0));
+        queue.push(1).unwrap();
+
+        // A producer crashed after taking ticket 2, before writing
+        let ticket = queue.obtain_push_ticket().unwrap();
+        assert_eq!(ticket, 2);
+        queue.push(3).unwrap();
+
+        // Slot 0 was handed over to ticket 4, slot 2 still waits for the push of ticket 2
+        assert_eq!(queue.occupancy_snapshot(), vec![(false, 8), (true, 3), (false, 4), (true, 7)]);
+        assert_eq!(queue.len(), 3);
+    }
+
+    #[test]
+    fn try_push_detailed() {
+        let queue: StaticSpinQueue<usize, 2> = Default::default();
+        let producer = queue.producer().unwrap();
+
+        producer.try_push_detailed(0).unwrap();
+        producer.try_push_detailed(1).unwrap();
+        let err = producer.try_push_detailed(2).unwrap_err();
+        assert_eq!(err, PushError { item: 2, kind: PushErrorKind::Full, len: 2, capacity: 2 });
+
+        // Full is momentary
+        assert_eq!(queue.pop(), Some(0));
+        producer.try_push_detailed(err.into_inner()).unwrap();
+
+        queue.close();
+        assert_eq!(queue.pop(), Some(1));
+        let err = producer.try_push_detailed(3).unwrap_err();
+        assert_eq!((err.kind, err.len), (PushErrorKind::Closed, 1));
+        assert_eq!(producer.push(3), Err(3));
+    }
+
+    #[test]
+    fn overwrite_push() {
+        use std::rc::Rc;
+
+        #[derive(Debug)]
+        struct Counted(usize, Rc<()>);
+
+        let alive = Rc::new(());
+        let queue: StaticSpinQueue<Counted, 4> = Default::default();
+        let producer = queue.producer().unwrap();
+        let consumer = queue.consumer().unwrap();
+
+        for i in 0..7 {
+            producer.overwrite_push(Counted(i, alive.clone())).unwrap();
+        }
+        // The three oldest were dropped on the way
+        assert_eq!(Rc::strong_count(&alive), 5);
+        assert_eq!(queue.len(), 4);
+
+        let seen: Vec<_> = consumer.drain().map(|c| c.0).collect();
+        assert_eq!(seen, vec![3, 4, 5, 6]);
+        assert_eq!(Rc::strong_count(&alive), 1);
+
+        // Plain push still fails on a full queue
+        for i in 0..4 {
+            producer.push(Counted(i, alive.clone())).unwrap();
+        }
+        assert!(producer.push(Counted(4, alive.clone())).is_err());
+
+        queue.close();
+        assert_eq!(producer.overwrite_push(Counted(5, alive)).unwrap_err().0, 5);
+        assert_eq!(queue.len(), 4);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn stats() {
+        let queue: StaticSpinQueue<usize, 4> = Default::default();
+        assert_eq!(queue.stats(), QueueStats::default());
+
+        for i in 0..7 {
+            let _ = queue.push(i);
+        }
+        assert_eq!(queue.pop(), Some(0));
+        assert!(queue.push(7).is_ok());
+        assert!(queue.push(8).is_err());
+
+        let mut items = vec![9, 10];
+        assert_eq!(queue.push_slice(&mut items), 0);
+
+        let mut out = Vec::new();
+        assert_eq!(queue.pop_batch(&mut out, 8), 4);
+        assert_eq!(queue.pop(), None);
+
+        assert_eq!(queue.stats(), QueueStats {
+            pushes: 5,
+            full_pushes: 5,
+            pops: 5,
+            empty_pops: 1,
+        });
+    }
+
+    #[test]
+    fn ticket_wraparound() {
+        let queue: StaticSpinQueue<usize, 4> = Default::default();
+        queue.push_ticket.store(StaticSpinQueue::<usize, 4>::TICKETS - 5, Ordering::Release);
+        unsafe { queue.reinit_slots() };
+
+        let mut next_push = 0;
+        let mut next_pop = 0;
+        for _ in 0..6 {
+            for _ in 0..3 {
+                queue.push(next_push).unwrap();
+                next_push += 1;
+            }
+            assert_eq!(queue.len(), 3);
+
+            for _ in 0..3 {
+                assert_eq!(queue.pop(), Some(next_pop));
+                next_pop += 1;
+            }
+            assert!(queue.is_empty());
+        }
+        // Well past the wrap by now
+        assert!(queue.push_ticket.load(Ordering::Acquire) < 16);
+
+        // Batches straddling it
+        let queue: HeapQueue<usize, super::super::sequencer::SpinSequencer> = HeapQueue::with_capacity(8);
+        queue.push_ticket.store(HeapQueue::<usize, super::super::sequencer::SpinSequencer>::TICKETS - 2, Ordering::Release);
+        unsafe { queue.reinit_slots() };
+
+        let mut items: Vec<usize> = (0..10).collect();
+        assert_eq!(queue.push_slice(&mut items), 8);
+        assert_eq!(queue.len(), 8);
+        assert!(queue.push(8).is_err());
+
+        let mut out = Vec::new();
+        assert_eq!(queue.pop_batch(&mut out, 5), 5);
+        assert_eq!(queue.push_slice(&mut items), 2);
+        assert_eq!(queue.pop_batch(&mut out, 10), 5);
+        assert_eq!(out, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn arc_handles() {
+        const COUNT: usize = 10000;
+
+        let queue: Arc<StaticSpinQueue<usize, 4>> = Arc::new(Default::default());
+        let watch = Arc::downgrade(&queue);
+
+        let producer = queue.clone().arc_producer().unwrap();
+        let consumer = queue.clone().arc_consumer().unwrap();
+        assert_eq!(queue.handles().count(Handle::Producer), 1);
+
+        queue.handles().set_max(1);
+        assert!(queue.clone().arc_producer().is_none());
+        drop(queue);
+
+        let pth = std::thread::spawn(move || {
+            for i in 0..COUNT {
+                while producer.push(i).is_err() {
+                    std::thread::yield_now();
+                }
+            }
+            producer.queue().close();
+        });
+
+        let cth = std::thread::spawn(move || {
+            let mut expected = 0;
+            loop {
+                match consumer.try_pop() {
+                    Pop::Item(i) => {
+                        assert_eq!(i, expected);
+                        expected += 1;
+                    }
+                    Pop::Empty => std::thread::yield_now(),
+                    Pop::Closed => break,
+                }
+            }
+            expected
+        });
+
+        pth.join().unwrap();
+        assert_eq!(cth.join().unwrap(), COUNT);
+
+        // The last handle took the queue with it
+        assert!(watch.upgrade().is_none());
     }
-    unsafe fn unsync_pop(&self) -> Option<Self::Item> {
-        self.pop()
+
+    #[test]
+    fn debug() {
+        let queue: StaticSpinQueue<core::cell::Cell<usize>, 4> = Default::default();
+        queue.push(core::cell::Cell::new(0)).ok().unwrap();
+
+        let formatted = format!("{:?}", queue);
+        assert!(formatted.starts_with("StaticQueue"));
+        assert!(formatted.contains("len: 1"));
+        assert!(!format!("{:?}", queue.producer().unwrap()).is_empty());
+        assert!(!format!("{:?}", queue.consumer().unwrap()).is_empty());
     }
 
-    fn push(&self, t: Self::Item) -> Result<(), Self::Item>;
-    fn pop(&self) -> Option<Self::Item>;
+    #[test]
+    fn heap_queue() {
+        let queue: HeapQueue<usize, super::super::sequencer::SpinSequencer> = HeapQueue::with_capacity(7);
+        assert_eq!(queue.capacity(), 7);
 
-    fn producer<'a>(&'a self) -> Producer<'a, Self> where Self: Sized{
-        Producer {
-            queue: self,
+        // Not a power of two, so the ticket arithmetic really runs on the runtime capacity
+        for round in 0..5 {
+            for i in 0..7 {
+                queue.push(round * 7 + i).unwrap();
+            }
+            assert_eq!(queue.push(100), Err(100));
+            assert_eq!(queue.len(), 7);
+
+            for i in 0..7 {
+                assert_eq!(queue.pop(), Some(round * 7 + i));
+            }
+            assert_eq!(queue.pop(), None);
         }
+
+        assert!(format!("{:?}", queue).starts_with("HeapQueue"));
     }
 
-    fn consumer<'a>(&'a self) -> Consumer<'a, Self> where Self: Sized {
-        Consumer {
-            queue: self,
-        }
+    #[test]
+    #[should_panic(expected = "capacity of at least 1")]
+    fn heap_queue_empty() {
+        let _: HeapQueue<usize, super::super::sequencer::SpinSequencer> = HeapQueue::with_capacity(0);
     }
-}
 
-pub struct StaticQueue<T, S: Sequencer, const N: usize> {
-    slots: [Slot<T, S>; {N}],
+    #[test]
+    fn reinit_slots() {
+        let queue: StaticSpinQueue<usize, 4> = Default::default();
 
-    push_ticket: AtomicUsize,
-    pop_ticket: AtomicUsize,
-}
+        // A pop that took its ticket and never got to the slot, item 0 stays behind
+        queue.push(0).unwrap();
+        queue.push(1).unwrap();
+        queue.pop_ticket.fetch_add(1, Ordering::AcqRel);
+        assert_eq!(queue.pop(), Some(1));
 
-impl<T, S: Sequencer, const N: usize> StaticQueue<T, S, {N}> {
-    fn obtain_push_ticket(&self) -> Option<usize> {
-        loop {
-            let cur_push = self.push_ticket.load(Ordering::Acquire);
-            let cur_pop = self.pop_ticket.load(Ordering::Acquire);
+        // A push that took its ticket and never filled the slot
+        queue.push_ticket.fetch_add(1, Ordering::AcqRel);
+        assert_eq!(queue.len(), 1);
 
-            let size = cur_push as isize - cur_pop as isize;
-            // Queue is full
-            if size >= {N} as isize {
-                break None;
-            }
+        unsafe { queue.reinit_slots() };
+        assert!(queue.is_empty());
+        assert_eq!(queue.pop(), None);
 
-            // TODO: do we need Release here?
-            if self.push_ticket.compare_and_swap(cur_push, cur_push + 1, Ordering::AcqRel) == cur_push {
-                break Some(cur_push);
+        // Going around the ring a few times would hang on either desynced slot otherwise
+        for round in 0..4 {
+            for i in 0..4 {
+                queue.push(round * 4 + i).unwrap();
+            }
+            assert_eq!(queue.push(100), Err(100));
+            for i in 0..4 {
+                assert_eq!(queue.pop(), Some(round * 4 + i));
             }
         }
+        assert_eq!(queue.pop(), None);
     }
 
-    fn obtain_pop_ticket(&self) -> Option<usize> {
-        loop {
-            let cur_pop = self.pop_ticket.load(Ordering::Acquire);
-            let cur_push = self.push_ticket.load(Ordering::Acquire);
+    #[test]
+    fn iter() {
+        let queue: StaticSpinQueue<usize, 8> = Default::default();
+        let producer = queue.producer().unwrap();
+        let mut consumer = queue.consumer().unwrap();
 
-            if cur_pop >= cur_push {
-                // It's possible that cur_pop > cur_push because hey, memory ordering.
-                // Maybe a race between three threads?
+        for i in 0..6 {
+            producer.push(i).unwrap();
+        }
 
-                return None;
-            }
+        let head: Vec<usize> = consumer.by_ref().take(2).collect();
+        assert_eq!(head, vec![0, 1]);
 
-            // TODO: do we need Release here?
-            if self.pop_ticket.compare_and_swap(cur_pop, cur_pop + 1, Ordering::AcqRel) == cur_pop {
-                break Some(cur_pop);
-            }
+        let drained: Vec<usize> = consumer.drain().collect();
+        assert_eq!(drained, vec![2, 3, 4, 5]);
+        assert_eq!(consumer.drain().next(), None);
+
+        // Items pushed after drain() was called are left alone
+        producer.push(6).unwrap();
+        let mut drain = consumer.drain();
+        producer.push(7).unwrap();
+        assert_eq!(drain.next(), Some(6));
+        assert_eq!(drain.next(), None);
+
+        let mut rest = Vec::new();
+        for i in consumer {
+            rest.push(i);
         }
+        assert_eq!(rest, vec![7]);
     }
-}
 
-impl<T, S: Sequencer, const N: usize> Queue for StaticQueue<T, S, {N}> {
-    type Item = T;
+    #[test]
+    fn max_handles() {
+        let queue: StaticSpinQueue<usize, 4> = Default::default();
+        queue.handles().set_max(2);
 
-    fn pop(&self) -> Option<Self::Item> {
-        let ticket = self.obtain_pop_ticket()?;
+        let first = queue.consumer().unwrap();
+        let second = queue.consumer().unwrap();
+        assert_eq!(queue.handles().count(Handle::Consumer), 2);
+        assert!(queue.consumer().is_none());
+
+        // Producers are counted separately
+        let producer = queue.producer().unwrap();
+        assert_eq!(queue.handles().count(Handle::Producer), 1);
 
-        let offset = ticket % N;
-        let seq = ticket / N;
+        drop(first);
+        assert_eq!(queue.handles().count(Handle::Consumer), 1);
+        let third = queue.consumer().unwrap();
+        assert!(queue.consumer().is_none());
+
+        producer.push(1).unwrap();
+        assert_eq!(third.pop(), Some(1));
 
-        Some(self.slots[offset].pop(seq))
+        drop(second);
+        drop(third);
+        drop(producer);
+        assert_eq!(queue.handles().count(Handle::Consumer), 0);
+        assert_eq!(queue.handles().count(Handle::Producer), 0);
     }
 
-    fn push(&self, t: Self::Item) -> Result<(), Self::Item> {
-        let ticket = match self.obtain_push_ticket() {
-            None => return Err(t),
-            Some(ticket) => ticket,
-        };
+    #[test]
+    fn max_handles_try_clone() {
+        let queue: StaticSpinQueue<usize, 4> = Default::default();
+        queue.handles().set_max(2);
 
-        let offset = ticket % N;
-        let seq = ticket / N;
+        let consumer = queue.consumer().unwrap();
+        let copy = consumer.try_clone().unwrap();
+        assert!(consumer.try_clone().is_none());
+        assert!(copy.try_clone().is_none());
 
-        self.slots[offset].push(t, seq);
+        // Room again once one is dropped
+        drop(copy);
+        let _copy = consumer.try_clone().unwrap();
+        assert_eq!(queue.handles().count(Handle::Consumer), 2);
 
-        Ok(())
+        let queue: Arc<StaticSpinQueue<usize, 4>> = Arc::new(Default::default());
+        queue.handles().set_max(2);
+        let producer = queue.clone().arc_producer().unwrap();
+        let _copy = producer.try_clone().unwrap();
+        assert!(producer.try_clone().is_none());
     }
-}
 
-impl<T, S: Sequencer, const N: usize> Default for StaticQueue<T, S, {N}> {
-    fn default() -> Self {
-        unsafe { MaybeUninit::zeroed().assume_init() }
+    #[test]
+    fn peek() {
+        let queue: StaticSpinQueue<usize, 4> = Default::default();
+        let producer = queue.producer().unwrap();
+        let mut consumer = unsafe { SpscConsumer::new(&queue) };
+
+        assert_eq!(consumer.peek(), None);
+
+        // Wraps around the slots a couple of times
+        for i in 0..10 {
+            producer.push(i).unwrap();
+            producer.push(i + 100).unwrap();
+
+            assert_eq!(consumer.peek(), Some(&i));
+            assert_eq!(consumer.peek(), Some(&i));
+            assert_eq!(queue.len(), 2);
+
+            assert_eq!(consumer.pop(), Some(i));
+            assert_eq!(consumer.peek(), Some(&(i + 100)));
+            assert_eq!(consumer.pop(), Some(i + 100));
+            assert_eq!(consumer.peek(), None);
+        }
+
+        queue.close();
+        assert_eq!(consumer.try_pop(), Pop::Closed);
     }
-}
 
-#[derive(Clone)]
-pub struct Consumer<'a, Q: Queue> {
-    queue: &'a Q,
-}
+    #[test]
+    fn pop_timeout() {
+        use super::super::sequencer::CondvarSequencer;
+        use std::time::Duration;
 
-#[derive(Clone)]
-pub struct Producer<'a, Q: Queue> {
-    queue: &'a Q,
-}
+        let queue: StaticQueue<usize, CondvarSequencer, 4> = Default::default();
+        let consumer = queue.consumer().unwrap();
+        assert_eq!(consumer.pop_timeout(Duration::from_millis(1)), Ok(None));
 
-impl<'a, Q: Queue> Consumer<'a, Q> {
-    pub fn pop(&self) -> Option<Q::Item> {
-        self.queue.pop()
+        queue.push(0).unwrap();
+        assert_eq!(consumer.pop_timeout(Duration::from_millis(1)), Ok(Some(0)));
+
+        // A producer took its ticket and never wrote
+        let ticket = queue.obtain_push_ticket().unwrap();
+        let pending = consumer.pop_timeout(Duration::from_millis(20)).unwrap_err();
+        let pending = pending.resume(Duration::from_millis(1)).unwrap_err();
+        assert_eq!(pending.ticket, ticket);
+
+        // It shows up after all
+        let (slot, _) = queue.slot(ticket);
+        slot.push(1, ticket);
+        assert_eq!(pending.resume(Duration::from_millis(1)).unwrap(), 1);
+
+        // And the slot is usable again on the next round
+        for i in 2..10 {
+            queue.push(i).unwrap();
+            assert_eq!(consumer.pop_timeout(Duration::from_millis(1)), Ok(Some(i)));
+        }
     }
-}
 
-impl<'a, Q: Queue> Producer<'a, Q> {
-    pub fn push(&self, data: Q::Item) -> Result<(), Q::Item> {
-        self.queue.push(data)
+    #[test]
+    fn drain_filter() {
+        let queue: StaticSpinQueue<usize, 8> = Default::default();
+        let producer = queue.producer().unwrap();
+        let mut consumer = queue.consumer().unwrap();
+
+        // Start off the beginning of the ring, so putting items back wraps around
+        producer.push(100).unwrap();
+        consumer.pop().unwrap();
+        for i in 0..7 {
+            producer.push(i).unwrap();
+        }
+
+        let evens = consumer.drain_filter(|i| i % 2 == 0);
+        assert_eq!(evens, vec![0, 2, 4, 6]);
+
+        let odds: Vec<usize> = consumer.drain().collect();
+        assert_eq!(odds, vec![1, 3, 5]);
+
+        // Closed queues refuse the items put back, they come out at the end of the result
+        for i in 0..4 {
+            producer.push(i).unwrap();
+        }
+        queue.close();
+        assert_eq!(consumer.drain_filter(|&i| i >= 2), vec![2, 3, 0, 1]);
+        assert_eq!(consumer.try_pop(), Pop::Closed);
     }
-}
 
-pub type StaticSpinQueue<T, const N: usize> = StaticQueue<T, super::sequencer::SpinSequencer, {N}>;
+    #[test]
+    fn transfer() {
+        let src: StaticSpinQueue<usize, 8> = Default::default();
+        let dst: StaticSpinQueue<usize, 4> = Default::default();
 
-#[cfg(test)]
-mod test {
-    use super::*;
+        for i in 1..=6 {
+            src.push(i).unwrap();
+        }
+
+        assert_eq!(src.transfer(&dst, 3), 3);
+        assert_eq!(src.transfer(&dst, 10), 1);
+
+        for i in 1..=4 {
+            assert_eq!(dst.pop(), Some(i));
+        }
+        assert_eq!(dst.pop(), None);
+
+        // 5 was popped but dst was full, so it went back to the tail
+        assert_eq!(src.pop(), Some(6));
+        assert_eq!(src.pop(), Some(5));
+        assert_eq!(src.pop(), None);
+
+        src.push(7).unwrap();
+        assert_eq!(src.transfer(&dst, 10), 1);
+        assert_eq!(dst.pop(), Some(7));
+    }
 
     #[test]
-    fn basic() {
-        let queue: StaticSpinQueue<usize, 4> = Default::default();
+    fn push_notify() {
+        const P_COUNT: usize = 4;
+        const P_ITER: usize = 16;
+        const ROUNDS: usize = 32;
 
-        let mut producer = queue.producer();
-        let mut consumer = queue.consumer();
+        let queue: Box<StaticSpinQueue<usize, 64>> = Default::default();
+        let queue = Box::leak(queue);
+        let edges = Box::leak(Box::new(AtomicUsize::new(0)));
 
-        producer.push(1).unwrap();
-        producer.push(2).unwrap();
-        producer.push(3).unwrap();
-        producer.push(4).unwrap();
-        let data = producer.push(5).unwrap_err();
-        assert_eq!(data, 5);
+        let notify = |edges: &AtomicUsize| { edges.fetch_add(1, Ordering::Relaxed); };
 
-        assert_eq!(consumer.pop(), Some(1));
-        assert_eq!(consumer.pop(), Some(2));
-        assert_eq!(consumer.pop(), Some(3));
+        queue.push_notify(0, || notify(edges)).unwrap();
+        queue.push_notify(1, || notify(edges)).unwrap();
+        assert_eq!(edges.load(Ordering::Relaxed), 1);
+        queue.pop().unwrap();
+        queue.push_notify(2, || notify(edges)).unwrap();
+        assert_eq!(edges.load(Ordering::Relaxed), 1);
+        queue.pop().unwrap();
+        queue.pop().unwrap();
+        queue.push_notify(3, || notify(edges)).unwrap();
+        assert_eq!(edges.load(Ordering::Relaxed), 2);
+        queue.pop().unwrap();
 
-        producer.push(5).unwrap();
-        producer.push(6).unwrap();
-        producer.push(7).unwrap();
-        let data = producer.push(8).unwrap_err();
-        assert_eq!(data, 8);
+        // Concurrent pushes into an empty queue make exactly one transition
+        for round in 0..ROUNDS {
+            let mut pths = Vec::with_capacity(P_COUNT);
+            for _ in 0..P_COUNT {
+                let producer = queue.producer().unwrap();
+                let edges = &*edges;
+                pths.push(std::thread::spawn(move || {
+                    for i in 0..P_ITER {
+                        producer.push_notify(i, || notify(edges)).unwrap();
+                    }
+                }));
+            }
 
-        assert_eq!(consumer.pop(), Some(4));
-        assert_eq!(consumer.pop(), Some(5));
-        assert_eq!(consumer.pop(), Some(6));
-        assert_eq!(consumer.pop(), Some(7));
-        assert_eq!(consumer.pop(), None);
-        assert_eq!(consumer.pop(), None);
-        assert_eq!(consumer.pop(), None);
+            for p in pths.into_iter() {
+                p.join().unwrap();
+            }
 
-        producer.push(8).unwrap();
-        assert_eq!(consumer.pop(), Some(8));
+            assert_eq!(edges.load(Ordering::Relaxed), round + 3);
+            assert_eq!(queue.len(), P_COUNT * P_ITER);
+            while queue.pop().is_some() {}
+        }
     }
 
     #[test]
@@ -181,8 +1818,8 @@ mod test {
         let queue: Box<StaticSpinQueue<usize, 128>> = Default::default();
         let queue = Box::leak(queue);
 
-        let mut producer = queue.producer();
-        let mut consumer = queue.consumer();
+        let mut producer = queue.producer().unwrap();
+        let mut consumer = queue.consumer().unwrap();
 
         let pth = std::thread::spawn(move || {
             for i in RANGE {
@@ -210,28 +1847,196 @@ mod test {
         cth.join().unwrap();
     }
 
+    #[test]
+    fn batch() {
+        let queue: StaticSpinQueue<usize, 4> = Default::default();
+
+        let mut items: Vec<usize> = (0..6).collect();
+        assert_eq!(queue.push_slice(&mut items), 4);
+        assert_eq!(items, vec![4, 5]);
+        assert_eq!(queue.push_slice(&mut items), 0);
+
+        let mut out = Vec::new();
+        assert_eq!(queue.pop_batch(&mut out, 3), 3);
+        assert_eq!(out, vec![0, 1, 2]);
+
+        // Only room for two, the run is cut at the capacity boundary
+        queue.push(6).unwrap();
+        items.push(7);
+        assert_eq!(queue.push_slice(&mut items), 2);
+        assert_eq!(items, vec![7]);
+
+        out.clear();
+        assert_eq!(queue.pop_batch(&mut out, 10), 4);
+        assert_eq!(out, vec![3, 6, 4, 5]);
+        assert_eq!(queue.pop_batch(&mut out, 10), 0);
+        assert_eq!(queue.pop_batch(&mut out, 0), 0);
+    }
+
+    #[test]
+    fn batch_mpmc() {
+        const P_COUNT: usize = 4;
+        const P_ITER: usize = 4096;
+        const BATCH: usize = 7;
+        const C_COUNT: usize = 4;
+
+        let queue: Box<StaticSpinQueue<usize, 16>> = Default::default();
+        let queue = Box::leak(queue);
+        let pending_producer = Box::leak(Box::new(AtomicUsize::new(P_COUNT)));
+
+        let mut pths = Vec::with_capacity(P_COUNT);
+        for p in 0..P_COUNT {
+            let producer = queue.producer().unwrap();
+            let ppcnt = &*pending_producer;
+            pths.push(std::thread::spawn(move || {
+                let mut items = Vec::with_capacity(BATCH);
+                let mut next = 0;
+
+                // Mix batched and single pushes, so both kinds of reservations race
+                while next < P_ITER || !items.is_empty() {
+                    while items.len() < BATCH && next < P_ITER {
+                        items.push(p * P_ITER + next);
+                        next += 1;
+                    }
+
+                    if next % 3 == 0 {
+                        if let Some(&item) = items.first() {
+                            if producer.push(item).is_ok() {
+                                items.remove(0);
+                            }
+                        }
+                    } else {
+                        producer.push_slice(&mut items);
+                    }
+                }
+
+                ppcnt.fetch_sub(1, Ordering::Release);
+            }));
+        }
+
+        let mut cths = Vec::with_capacity(C_COUNT);
+        for _ in 0..C_COUNT {
+            let consumer = queue.consumer().unwrap();
+            let ppcnt = &*pending_producer;
+            cths.push(std::thread::spawn(move || {
+                let mut out = Vec::new();
+                let mut last = [None; P_COUNT];
+
+                let mut check = |out: &mut Vec<usize>| {
+                    // Items from the same producer must show up in order
+                    for i in out.drain(..) {
+                        let p = i / P_ITER;
+                        assert!(last[p].map_or(true, |l| l < i), "{} after {:?}", i, last[p]);
+                        last[p] = Some(i);
+                    }
+                };
+
+                let mut got = Vec::new();
+                while ppcnt.load(Ordering::Acquire) != 0 {
+                    consumer.pop_batch(&mut out, BATCH);
+                    got.extend_from_slice(&out);
+                    check(&mut out);
+                }
+
+                while consumer.pop_batch(&mut out, BATCH) != 0 {
+                    got.extend_from_slice(&out);
+                    check(&mut out);
+                }
+
+                got
+            }));
+        }
+
+        for p in pths.into_iter() {
+            p.join().unwrap();
+        }
+
+        let mut tot = vec![0u8; P_COUNT * P_ITER];
+        for c in cths.into_iter() {
+            for i in c.join().unwrap() {
+                tot[i] += 1;
+            }
+        }
+
+        for (i, cnt) in tot.into_iter().enumerate() {
+            assert_eq!(cnt, 1, "Failed key: {}", i);
+        }
+    }
+
     lazy_static::lazy_static! {
         static ref MPMC_QUEUE: Box<StaticSpinQueue<usize, 1>> = box Default::default();
     }
 
     #[test]
     fn mpmc() {
+        run_mpmc(&**MPMC_QUEUE);
+    }
+
+    #[test]
+    fn mpmc_backoff() {
+        use super::super::backoff::{ExponentialBackoff, SpinBackoff, YieldBackoff};
+        use super::super::sequencer::SpinSequencer;
+
+        run_mpmc::<SpinBackoff>(Box::leak(box Default::default()));
+        run_mpmc::<ExponentialBackoff>(Box::leak(box Default::default()));
+        run_mpmc::<YieldBackoff>(Box::leak(box Default::default()));
+
+        let queue: StaticBackoffQueue<usize, SpinSequencer, ExponentialBackoff, 4> = Default::default();
+        queue.push(0).unwrap();
+        assert_eq!(queue.pop(), Some(0));
+    }
+
+    // Every item popped exactly once, by consumers racing each other for the pop tickets
+    #[test]
+    fn contended_pops() {
+        const COUNT: usize = 5000;
+        const C_COUNT: usize = 8;
+        const ITERATIONS: usize = 20;
+
+        for _ in 0..ITERATIONS {
+            let queue: Arc<StaticSpinQueue<usize, 16>> = Arc::new(Default::default());
+
+            let consumers: Vec<_> = (0..C_COUNT).map(|_| {
+                let consumer = queue.clone().arc_consumer().unwrap();
+                std::thread::spawn(move || {
+                    let mut popped = Vec::new();
+                    loop {
+                        match consumer.try_pop() {
+                            Pop::Item(i) => popped.push(i),
+                            Pop::Empty => std::thread::yield_now(),
+                            Pop::Closed => break popped,
+                        }
+                    }
+                })
+            }).collect();
+
+            let producer = queue.clone().arc_producer().unwrap();
+            for i in 0..COUNT {
+                while producer.push(i).is_err() {
+                    std::thread::yield_now();
+                }
+            }
+            queue.close();
+
+            let mut popped: Vec<usize> = consumers.into_iter().flat_map(|c| c.join().unwrap()).collect();
+            popped.sort();
+            assert_eq!(popped, (0..COUNT).collect::<Vec<_>>());
+        }
+    }
+
+    fn run_mpmc<K: Backoff + 'static>(queue: &'static StaticBackoffQueue<usize, super::super::sequencer::SpinSequencer, K, 1>) {
         const LIMIT: usize = 1;
         const RANGE: core::ops::Range<usize> = 0usize..LIMIT;
         const P_COUNT: usize = 4;
         const P_ITER: usize = 16;
         const C_COUNT: usize = 8;
-        const C_CHECK_INTERVAL: usize = 128;
         // const P_YIELD_INTERVAL: usize = 512;
 
         let mut tot = box [0u8; LIMIT];
 
-        let pending_producer = Box::leak(Box::new(AtomicUsize::new(P_COUNT)));
-
         let mut pths = Vec::with_capacity(P_COUNT);
         for _ in 0..P_COUNT {
-            let mut producer = MPMC_QUEUE.producer();
-            let ppcnt = &*pending_producer;
+            let producer = queue.producer().unwrap();
             pths.push(std::thread::spawn(move || {
                 let mut fail_cnt = 0;
 
@@ -256,8 +2061,6 @@ mod test {
                     }
                 }
 
-                ppcnt.fetch_sub(1, Ordering::Release);
-
                 fail_cnt
             }));
         }
@@ -265,28 +2068,18 @@ mod test {
         let mut cths = Vec::with_capacity(C_COUNT);
 
         for _ in 0..C_COUNT {
-            let mut consumer = MPMC_QUEUE.consumer();
+            let consumer = queue.consumer().unwrap();
             let counter = Box::leak(box [0u8; LIMIT]);
-            let ppcnt = &*pending_producer;
             cths.push(std::thread::spawn(move || {
+                // Until the queue is closed and drained
                 loop {
-                    let pending = ppcnt.load(Ordering::Acquire);
-                    if pending == 0 {
-                        break;
-                    }
-
-                    for _ in 0..C_CHECK_INTERVAL {
-                        if let Some(i) = consumer.pop() {
-                            counter[i] += 1;
-                        }
+                    match consumer.try_pop() {
+                        Pop::Item(i) => counter[i] += 1,
+                        Pop::Empty => continue,
+                        Pop::Closed => break,
                     }
                 }
 
-                // Until consumer is empty
-                while let Some(i) = consumer.pop() {
-                    counter[i] += 1;
-                }
-
                 counter
             }))
         }
@@ -296,6 +2089,9 @@ mod test {
             println!("Producer {} fail count: {}", idx, cnt);
         }
 
+        // All producers are done
+        queue.close();
+
         for c in cths.into_iter() {
             let cnt = c.join().unwrap();
             for i in RANGE {
@@ -310,3 +2106,92 @@ mod test {
         }
     }
 }
+
+// Every interleaving, under the C11 memory model. Only these make sense with the loom feature,
+//   the other tests touch loom's atomics outside of a model and panic:
+//   cargo test --release --features loom loom
+#[cfg(all(test, feature = "loom"))]
+mod loom_test {
+    use super::*;
+    use super::super::sequencer::SpinSequencer;
+
+    use loom::sync::Arc;
+    use loom::thread;
+
+    #[test]
+    fn loom_mpmc() {
+        let mut builder = loom::model::Builder::new();
+        // Enough to reorder every ticket grab against the slot accesses, while keeping the run short
+        builder.preemption_bound = Some(3);
+
+        builder.check(|| {
+            let queue: Arc<StaticQueue<usize, SpinSequencer, 2>> = Arc::new(Default::default());
+
+            let producers: Vec<_> = (0..2).map(|i| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    queue.push(i).unwrap();
+                })
+            }).collect();
+
+            // loom allows 4 threads including this one, which is the second consumer
+            let consumer = {
+                let queue = queue.clone();
+                thread::spawn(move || queue.pop())
+            };
+            let mut seen: Vec<usize> = queue.pop().into_iter().collect();
+
+            for producer in producers {
+                producer.join().unwrap();
+            }
+            seen.extend(consumer.join().unwrap());
+
+            // Whatever the consumers missed before the producers were done
+            while let Some(i) = queue.pop() {
+                seen.push(i);
+            }
+
+            seen.sort();
+            assert_eq!(seen, vec![0, 1]);
+        });
+    }
+
+    #[test]
+    fn loom_close() {
+        loom::model(|| {
+            let queue: Arc<StaticQueue<usize, SpinSequencer, 2>> = Arc::new(Default::default());
+
+            let producer = {
+                let queue = queue.clone();
+                thread::spawn(move || queue.push(0).is_ok())
+            };
+            let closer = {
+                let queue = queue.clone();
+                thread::spawn(move || queue.close())
+            };
+
+            let mut popped = false;
+            let closed = loop {
+                match queue.try_pop() {
+                    Pop::Item(_) => popped = true,
+                    Pop::Empty => thread::yield_now(),
+                    Pop::Closed => break true,
+                }
+                if popped {
+                    break false;
+                }
+            };
+
+            let pushed = producer.join().unwrap();
+            closer.join().unwrap();
+
+            // Closed is final: a push that landed was popped before it, or is still in the queue if we stopped at the item
+            if closed {
+                assert!(!pushed);
+                assert_eq!(queue.pop(), None);
+            } else {
+                assert!(pushed);
+            }
+        });
+    }
+}
@@ -1,10 +1,24 @@
-use core::sync::atomic::*;
+use crate::sync::{spin_loop_hint, AtomicUsize, Ordering};
+#[cfg(any(feature = "std", test))]
+use crate::sync::yield_now;
+#[cfg(all(target_os = "linux", not(feature = "loom")))]
+use crate::sync::AtomicU32;
 
+// Hands a slot back and forth between a push and a pop, the only synchronization a slot has.
+// Once wait_until(n) returns Ok, it must have synchronized-with the update_next(n) it saw,
+//   i.e. anything before that update_next happens-before whatever comes after the wait.
+// Release stores and Acquire loads, or a lock around the value, are enough.
 pub trait Sequencer: Default {
     fn wait_until(&self, sequence: usize, timeout: Option<core::time::Duration>) -> Result<(), ()>;
     fn update_next(&self, sequence: usize);
+    // The last sequence stored, for inspection only. Nothing is synchronized by reading it.
+    fn current(&self) -> usize;
 }
 
+// Sequencers whose wait_until gives up with Err once the timeout passes, instead of panicking on it.
+// Only these can back a pop_timeout.
+pub trait TimeoutSequencer: Sequencer {}
+
 #[derive(Default)]
 pub struct SpinSequencer {
     seq: AtomicUsize,
@@ -20,22 +34,93 @@ impl Sequencer for SpinSequencer {
             if self.seq.load(Ordering::Acquire) == sequence {
                 break Ok(());
             }
+            spin_loop_hint();
+        }
+    }
+
+    fn update_next(&self, sequence: usize) {
+        self.seq.store(sequence, Ordering::Release);
+    }
+
+    fn current(&self) -> usize {
+        self.seq.load(Ordering::Relaxed)
+    }
+}
+
+// Spins SPINS times, then yields the core between checks, so oversubscribed waiters let the thread
+//   they are waiting for run. The first SPINS checks are the same as SpinSequencer's, so a waiter with a core to itself,
+//   whose sequence comes up within the budget, never yields.
+// Without std there's nothing to yield to, and it keeps spinning. There's no clock either to tell when a timeout passes,
+//   so it's only a TimeoutSequencer with std, and like SpinSequencer it doesn't take a timeout without.
+#[derive(Default)]
+pub struct HybridSpinSequencer<const SPINS: usize> {
+    seq: AtomicUsize,
+}
+
+#[cfg(any(feature = "std", test))]
+impl<const SPINS: usize> TimeoutSequencer for HybridSpinSequencer<{SPINS}> {}
+
+impl<const SPINS: usize> Sequencer for HybridSpinSequencer<{SPINS}> {
+    fn wait_until(&self, sequence: usize, timeout: Option<core::time::Duration>) -> Result<(), ()> {
+        for _ in 0..SPINS {
+            if self.seq.load(Ordering::Acquire) == sequence {
+                return Ok(());
+            }
+            spin_loop_hint();
+        }
+
+        #[cfg(any(feature = "std", test))]
+        {
+            let deadline = timeout.map(|to| std::time::Instant::now() + to);
+            loop {
+                if self.seq.load(Ordering::Acquire) == sequence {
+                    break Ok(());
+                }
+
+                if let Some(deadline) = deadline {
+                    if std::time::Instant::now() >= deadline {
+                        break Err(());
+                    }
+                }
+                yield_now();
+            }
+        }
+
+        #[cfg(not(any(feature = "std", test)))]
+        {
+            if timeout.is_some() {
+                unimplemented!("No clock to time out on without std");
+            }
+
+            loop {
+                if self.seq.load(Ordering::Acquire) == sequence {
+                    break Ok(());
+                }
+                spin_loop_hint();
+            }
         }
     }
 
     fn update_next(&self, sequence: usize) {
         self.seq.store(sequence, Ordering::Release);
     }
+
+    fn current(&self) -> usize {
+        self.seq.load(Ordering::Relaxed)
+    }
 }
 
-#[cfg(feature="std")]
+#[cfg(any(feature="std", test))]
 #[derive(Debug, Default)]
 pub struct CondvarSequencer {
     seq: std::sync::Mutex<usize>,
     condvar: std::sync::Condvar,
 }
 
-#[cfg(feature="std")]
+#[cfg(any(feature="std", test))]
+impl TimeoutSequencer for CondvarSequencer {}
+
+#[cfg(any(feature="std", test))]
 impl Sequencer for CondvarSequencer {
     fn wait_until(&self, sequence: usize, timeout: Option<core::time::Duration>) -> Result<(), ()> {
         let cur = self.seq.lock().unwrap();
@@ -44,26 +129,294 @@ impl Sequencer for CondvarSequencer {
             return Ok(());
         }
 
-        let cond = |pending: &mut usize| { *pending == sequence };
+        // Keep waiting while the sequence hasn't been reached.
+        // Wakeups for other sequences and spurious ones just go back to sleep, for the remaining time only.
+        let cond = |pending: &mut usize| { *pending != sequence };
 
         match timeout {
             Some(to) => {
-                let (guard, toe) = self.condvar.wait_timeout_while(cur, to, cond).unwrap();
-                if toe.timed_out() {
-                    return Err(());
+                let (guard, _) = self.condvar.wait_timeout_while(cur, to, cond).unwrap();
+                // Decide on the value itself rather than on the timeout flag,
+                //   an update landing right at the deadline still counts
+                if *guard == sequence {
+                    Ok(())
                 } else {
-                    return Ok(());
+                    Err(())
                 }
             }
             None => {
-                self.condvar.wait_while(cur, cond).unwrap();
-                return Ok(());
+                let _guard = self.condvar.wait_while(cur, cond).unwrap();
+                Ok(())
             }
-        };
+        }
     }
 
     fn update_next(&self, sequence: usize) {
         *self.seq.lock().unwrap() = sequence;
         self.condvar.notify_all();
     }
+
+    fn current(&self) -> usize {
+        *self.seq.lock().unwrap()
+    }
+}
+
+// Blocks on the sequence word itself with the futex syscall, so waiting doesn't burn a core
+//   and a slot costs 8 bytes instead of a Mutex and a Condvar.
+// Parks on the address of a real atomic, so it's left out under loom.
+// The sequence is truncated to 32 bits. Slots only ever wait for the next few sequences,
+//   so two of them colliding modulo 2^32 isn't a concern.
+#[cfg(all(target_os = "linux", not(feature = "loom")))]
+#[derive(Debug, Default)]
+pub struct FutexSequencer {
+    seq: AtomicU32,
+    // Threads inside wait_until, so update_next can skip the wake syscall when nobody is parked
+    waiters: AtomicU32,
+}
+
+#[cfg(all(target_os = "linux", not(feature = "loom")))]
+impl FutexSequencer {
+    // Absolute CLOCK_MONOTONIC deadline, FUTEX_WAIT_BITSET takes it that way,
+    //   so wakeups for other sequences don't restart the timeout
+    fn deadline(timeout: core::time::Duration) -> libc::timespec {
+        let mut now = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+
+        let nsec = now.tv_nsec as u64 + timeout.subsec_nanos() as u64;
+        let sec = (now.tv_sec as u64)
+            .saturating_add(timeout.as_secs())
+            .saturating_add(nsec / 1_000_000_000);
+
+        libc::timespec {
+            tv_sec: core::cmp::min(sec, libc::time_t::max_value() as u64) as libc::time_t,
+            tv_nsec: (nsec % 1_000_000_000) as _,
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "loom")))]
+impl TimeoutSequencer for FutexSequencer {}
+
+#[cfg(all(target_os = "linux", not(feature = "loom")))]
+impl Sequencer for FutexSequencer {
+    fn wait_until(&self, sequence: usize, timeout: Option<core::time::Duration>) -> Result<(), ()> {
+        let target = sequence as u32;
+        if self.seq.load(Ordering::Acquire) == target {
+            return Ok(());
+        }
+
+        let deadline = timeout.map(Self::deadline);
+        let deadline_ptr = deadline.as_ref().map_or(core::ptr::null(), |d| d as *const libc::timespec);
+
+        // Pairs with update_next: either it sees us waiting, or we see its new sequence below
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+
+        let result = loop {
+            let cur = self.seq.load(Ordering::SeqCst);
+            if cur == target {
+                break Ok(());
+            }
+
+            // Sleeps only if seq still holds cur. Returns early on a wake, a changed value or a signal,
+            //   in which case we just look again.
+            let ret = unsafe {
+                libc::syscall(
+                    libc::SYS_futex,
+                    &self.seq as *const AtomicU32,
+                    libc::FUTEX_WAIT_BITSET | libc::FUTEX_PRIVATE_FLAG,
+                    cur,
+                    deadline_ptr,
+                    core::ptr::null::<u32>(),
+                    u32::max_value(), // FUTEX_BITSET_MATCH_ANY
+                )
+            };
+
+            if ret != 0 && unsafe { *libc::__errno_location() } == libc::ETIMEDOUT {
+                if self.seq.load(Ordering::Acquire) == target {
+                    break Ok(());
+                }
+                break Err(());
+            }
+        };
+
+        self.waiters.fetch_sub(1, Ordering::Relaxed);
+        result
+    }
+
+    fn update_next(&self, sequence: usize) {
+        self.seq.store(sequence as u32, Ordering::SeqCst);
+
+        if self.waiters.load(Ordering::SeqCst) != 0 {
+            // Everyone parked here is waiting for a different sequence, so wake them all to check
+            unsafe {
+                libc::syscall(
+                    libc::SYS_futex,
+                    &self.seq as *const AtomicU32,
+                    libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG,
+                    i32::max_value(),
+                );
+            }
+        }
+    }
+
+    // Only the low 32 bits survive, see above
+    fn current(&self) -> usize {
+        self.seq.load(Ordering::Relaxed) as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::queue::nonblocking::{Queue, StaticQueue};
+
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn condvar_timeout() {
+        let seq = CondvarSequencer::default();
+        assert_eq!(seq.wait_until(0, Some(Duration::from_millis(1))), Ok(()));
+
+        let start = Instant::now();
+        assert_eq!(seq.wait_until(2, Some(Duration::from_millis(20))), Err(()));
+        let waited = start.elapsed();
+        assert!(waited >= Duration::from_millis(20));
+        // Generous, the machine may be busy running other tests
+        assert!(waited < Duration::from_secs(2));
+
+        seq.update_next(2);
+        assert_eq!(seq.wait_until(2, Some(Duration::from_millis(20))), Ok(()));
+    }
+
+    #[test]
+    fn condvar_update_before_deadline() {
+        let seq = Arc::new(CondvarSequencer::default());
+
+        let updater = {
+            let seq = seq.clone();
+            std::thread::spawn(move || {
+                // Wakes the waiter for the wrong sequence first, it has to go back to sleep
+                std::thread::sleep(Duration::from_millis(50));
+                seq.update_next(1);
+                std::thread::sleep(Duration::from_millis(50));
+                seq.update_next(2);
+            })
+        };
+
+        let start = Instant::now();
+        assert_eq!(seq.wait_until(2, Some(Duration::from_secs(5))), Ok(()));
+        let waited = start.elapsed();
+        assert!(waited >= Duration::from_millis(100));
+        assert!(waited < Duration::from_secs(5));
+        updater.join().unwrap();
+
+        // Timed out waits leave the sequencer usable
+        assert_eq!(seq.wait_until(4, Some(Duration::from_millis(10))), Err(()));
+        seq.update_next(3);
+        seq.update_next(4);
+        assert_eq!(seq.wait_until(4, None), Ok(()));
+    }
+
+    #[test]
+    fn hybrid_spin() {
+        const COUNT: usize = 20000;
+
+        // No spin budget at all, every wait that doesn't succeed right away yields
+        let seq = HybridSpinSequencer::<0>::default();
+        assert_eq!(seq.wait_until(0, None), Ok(()));
+
+        // More busy pairs than cores, waiters have to give the core away for the others to make progress
+        let pairs: Vec<_> = (0..4).map(|_| {
+            let queue: &'static StaticQueue<usize, HybridSpinSequencer<64>, 4> = Box::leak(Box::new(Default::default()));
+            let producer = queue.producer().unwrap();
+            let consumer = queue.consumer().unwrap();
+
+            let pth = std::thread::spawn(move || {
+                for i in 0..COUNT {
+                    while producer.push(i).is_err() {
+                        std::thread::yield_now();
+                    }
+                }
+            });
+            let cth = std::thread::spawn(move || {
+                for i in 0..COUNT {
+                    loop {
+                        match consumer.pop() {
+                            Some(j) => {
+                                assert_eq!(i, j);
+                                break;
+                            }
+                            None => std::thread::yield_now(),
+                        }
+                    }
+                }
+            });
+            (pth, cth)
+        }).collect();
+
+        for (pth, cth) in pairs {
+            pth.join().unwrap();
+            cth.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn hybrid_spin_timeout() {
+        let seq = HybridSpinSequencer::<16>::default();
+        assert_eq!(seq.wait_until(2, Some(Duration::from_millis(10))), Err(()));
+        seq.update_next(2);
+        assert_eq!(seq.wait_until(2, Some(Duration::from_millis(10))), Ok(()));
+    }
+
+    #[cfg(all(target_os = "linux", not(feature = "loom")))]
+    #[test]
+    fn futex_timeout() {
+        let seq = Arc::new(FutexSequencer::default());
+        assert_eq!(seq.wait_until(0, Some(Duration::from_millis(1))), Ok(()));
+        assert_eq!(seq.wait_until(2, Some(Duration::from_millis(10))), Err(()));
+
+        // A wakeup for another sequence keeps the waiter parked until the deadline
+        let updater = {
+            let seq = seq.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(10));
+                seq.update_next(1);
+                std::thread::sleep(Duration::from_millis(10));
+                seq.update_next(2);
+            })
+        };
+        assert_eq!(seq.wait_until(2, Some(Duration::from_secs(10))), Ok(()));
+        updater.join().unwrap();
+    }
+
+    #[cfg(all(target_os = "linux", not(feature = "loom")))]
+    #[test]
+    fn futex_queue() {
+        const COUNT: usize = 100000;
+        let queue: &'static StaticQueue<usize, FutexSequencer, 4> = Box::leak(Box::new(Default::default()));
+
+        let producer = std::thread::spawn(move || {
+            let producer = queue.producer().unwrap();
+            for i in 0..COUNT {
+                while producer.push(i).is_err() {
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        let consumer = queue.consumer().unwrap();
+        let mut expected = 0;
+        while expected < COUNT {
+            if let Some(i) = consumer.pop() {
+                assert_eq!(i, expected);
+                expected += 1;
+            } else {
+                std::thread::yield_now();
+            }
+        }
+
+        producer.join().unwrap();
+        assert_eq!(consumer.pop(), None);
+    }
 }
@@ -1,7 +1,6 @@
 use super::sequencer::Sequencer;
 
-use core::cell::UnsafeCell;
-use core::sync::atomic::*;
+use crate::sync::{spin_loop_hint, AtomicBool, Ordering, UnsafeCell};
 use core::mem::MaybeUninit;
 
 pub struct Slot<T, S: Sequencer> {
@@ -10,38 +9,72 @@ pub struct Slot<T, S: Sequencer> {
     seq: S,
 }
 
+// The sequencer counts in tickets: ticket * 2 while waiting for that ticket's push, ticket * 2 + 1 once it's written.
+// Popping hands the slot over to the next ticket mapped onto it, which the queue passes in as `next`.
+// Everything wraps, so as long as the capacity divides 2^usize::BITS the slots keep working when the tickets wrap around.
 impl<T, S: Sequencer> Slot<T, S> {
-    pub fn push(&self, data: T, seq: usize) {
+    pub fn push(&self, data: T, ticket: usize) {
         // Wait until sequence number
         // timeout = None asserts Ok(())
-        // seq * 2 = push
-        self.seq.wait_until(seq * 2, None).unwrap();
+        // ticket * 2 = push
+        self.seq.wait_until(ticket.wrapping_mul(2), None).unwrap();
 
         // Wait until occupied = false
-        // It's highly improbable that we have so much thread preempeted
-        //   between pop_ticket -> pop and push_ticket -> push, that the ticket wraps around
-        // So we are doing a simple spin here.
-
-        // AcqRel, because we don't want it to be reordered before we got the sequence number,
-        //   and we don't want it to be reordered after we actually stores the data
-        // TODO: maybe we can make this one less strict? because seq.wait_until already has acquire schematic
-        while self.occupied.compare_and_swap(false, true, Ordering::AcqRel) { }
+        // The sequencer already did all the synchronizing: its wait_until synchronizes-with the update_next
+        //   of the pop that handed the slot over to this ticket, which happens after that pop read the data
+        //   and cleared occupied. So the flag is seen false right away, and our write below can't race with the read.
+        // Our write is published to the next pop by our own update_next, the flag takes no part in that either.
+        // Relaxed is all it needs. The flag stays as a cheap check, it would spin on a sequencer breaking that contract.
+        while self.occupied.compare_and_swap(false, true, Ordering::Relaxed) {
+            spin_loop_hint();
+        }
 
         // Now self.data is invalid memory. So we can write into it without dropping the data inside
-        unsafe{ core::ptr::write(self.data.get(), MaybeUninit::new(data)) };
+        self.data.with_mut(|ptr| unsafe { core::ptr::write(ptr, MaybeUninit::new(data)) });
 
         // Bump sequence number
-        self.seq.update_next(seq * 2 + 1);
+        self.seq.update_next(ticket.wrapping_mul(2).wrapping_add(1));
+    }
+
+    pub fn pop(&self, ticket: usize, next: usize) -> T {
+        self.seq.wait_until(ticket.wrapping_mul(2).wrapping_add(1), None).unwrap();
+        self.take(next)
     }
 
-    pub fn pop(&self, seq: usize) -> T {
-        self.seq.wait_until(seq * 2 + 1, None).unwrap();
-        let result = unsafe { core::ptr::read(self.data.get()).assume_init() };
+    // Like pop, but gives up after timeout if the push of `ticket` hasn't landed.
+    // The slot is left as it was, so the same ticket can be popped again later.
+    pub fn pop_timeout(&self, ticket: usize, next: usize, timeout: core::time::Duration) -> Option<T> {
+        self.seq.wait_until(ticket.wrapping_mul(2).wrapping_add(1), Some(timeout)).ok()?;
+        Some(self.take(next))
+    }
+
+    fn take(&self, next: usize) -> T {
+        let result = self.data.with(|ptr| unsafe { core::ptr::read(ptr).assume_init() });
         self.occupied.store(false, Ordering::Release);
-        self.seq.update_next(seq * 2 + 2);
+        self.seq.update_next(next.wrapping_mul(2));
 
         result
     }
+
+    // Wait for the push of `ticket` like pop does, but leave the item in place.
+    // The reference is only valid until the item is popped, the caller has to rule that out.
+    pub unsafe fn peek(&self, ticket: usize) -> &T {
+        self.seq.wait_until(ticket.wrapping_mul(2).wrapping_add(1), None).unwrap();
+        &*self.data.with(|ptr| (*ptr).as_ptr())
+    }
+
+    // Whether an item is being written or sits in the slot, and the raw sequence, see above.
+    // Leaves the data alone, it may well be uninitialized.
+    pub fn snapshot(&self) -> (bool, usize) {
+        (self.occupied.load(Ordering::Relaxed), self.seq.current())
+    }
+
+    // Mark the slot empty and waiting for the push of `ticket`. Whatever was stored is forgotten, not dropped.
+    // Only sound while nobody else is touching the slot.
+    pub unsafe fn reset(&self, ticket: usize) {
+        self.occupied.store(false, Ordering::Release);
+        self.seq.update_next(ticket.wrapping_mul(2));
+    }
 }
 
 unsafe impl<T, S: Sequencer> Send for Slot<T, S> {}
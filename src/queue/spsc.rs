@@ -0,0 +1,328 @@
+use super::nonblocking::Pop;
+
+use crate::util::init_array;
+
+use crate::sync::*;
+use core::mem::MaybeUninit;
+
+// Keeps the two indices on their own cache lines, so the producer and the consumer don't keep stealing each other's
+#[repr(align(64))]
+struct Padded<T>(T);
+
+// Ring for exactly one producer and one consumer. Each index only ever has one writer,
+//   so there is no ticket CAS and no per-slot sequencer: publishing an index with Release
+//   hands the slots before it over to the other side.
+// Indices count up and wrap, the slot is index % N. Like TicketQueue, power of two capacities
+//   keep working across the wraparound, others after 2^usize::BITS pushes.
+// Pushing and popping only goes through the pair split hands out, which is what keeps it to one of each.
+//   That's also why it isn't a Queue, anything with the queue itself could push or pop.
+pub struct SpscQueue<T, const N: usize> {
+    buf: [UnsafeCell<MaybeUninit<T>>; N],
+
+    // Written by the consumer only
+    pop_index: Padded<AtomicUsize>,
+    // Written by the producer only
+    push_index: Padded<AtomicUsize>,
+
+    closed: AtomicBool,
+}
+
+unsafe impl<T: Send, const N: usize> Send for SpscQueue<T, {N}> {}
+unsafe impl<T: Send, const N: usize> Sync for SpscQueue<T, {N}> {}
+
+impl<T, const N: usize> SpscQueue<T, {N}> {
+    pub fn new() -> Self {
+        Self {
+            buf: init_array(|| UnsafeCell::new(MaybeUninit::uninit())),
+            pop_index: Padded(AtomicUsize::new(0)),
+            push_index: Padded(AtomicUsize::new(0)),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    // The queue stays borrowed mutably while either end lives, so there's never a second pair.
+    // Once both are dropped it can be split again, picking up where the last pair left off.
+    pub fn split(&mut self) -> (Producer<'_, T, {N}>, Consumer<'_, T, {N}>) {
+        let queue: &Self = self;
+        (Producer { queue }, Consumer { queue })
+    }
+
+    // Only a snapshot, the ends may push or pop right after it's taken
+    pub fn len(&self) -> usize {
+        let pop = self.pop_index.0.load(Ordering::Acquire);
+        let push = self.push_index.0.load(Ordering::Acquire);
+
+        core::cmp::min(push.wrapping_sub(pop), N)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn slot(&self, index: usize) -> &UnsafeCell<MaybeUninit<T>> {
+        &self.buf[index % N]
+    }
+
+    // The producer's own index is only written by itself, Relaxed is enough to read it back
+    fn start_push(&self) -> Option<usize> {
+        let push = self.push_index.0.load(Ordering::Relaxed);
+        let pop = self.pop_index.0.load(Ordering::Acquire);
+        if push.wrapping_sub(pop) >= N || self.closed.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        Some(push)
+    }
+
+    fn finish_push(&self, push: usize, t: T) {
+        // The Acquire on pop_index in start_push ordered the consumer's read of this slot before our write
+        self.slot(push).with_mut(|ptr| unsafe { core::ptr::write(ptr, MaybeUninit::new(t)) });
+        self.push_index.0.store(push.wrapping_add(1), Ordering::Release);
+    }
+
+    fn pop(&self) -> Option<T> {
+        let pop = self.pop_index.0.load(Ordering::Relaxed);
+        if self.push_index.0.load(Ordering::Acquire) == pop {
+            return None;
+        }
+
+        let t = self.slot(pop).with(|ptr| unsafe { core::ptr::read(ptr).assume_init() });
+        self.pop_index.0.store(pop.wrapping_add(1), Ordering::Release);
+
+        Some(t)
+    }
+}
+
+impl<T, const N: usize> Default for SpscQueue<T, {N}> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for SpscQueue<T, {N}> {
+    fn drop(&mut self) {
+        let push = self.push_index.0.load(Ordering::Relaxed);
+        let mut pop = self.pop_index.0.load(Ordering::Relaxed);
+        while pop != push {
+            self.slot(pop).with_mut(|ptr| unsafe { core::ptr::drop_in_place((*ptr).as_mut_ptr()) });
+            pop = pop.wrapping_add(1);
+        }
+    }
+}
+
+impl<T, const N: usize> core::fmt::Debug for SpscQueue<T, {N}> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SpscQueue")
+            .field("push_index", &self.push_index.0.load(Ordering::Relaxed))
+            .field("pop_index", &self.pop_index.0.load(Ordering::Relaxed))
+            .field("capacity", &N)
+            .field("closed", &self.closed.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+// Pushing and popping take the end mutably, so a shared end can't be used to get a second producer or consumer
+pub struct Producer<'a, T, const N: usize> {
+    queue: &'a SpscQueue<T, {N}>,
+}
+
+pub struct Consumer<'a, T, const N: usize> {
+    queue: &'a SpscQueue<T, {N}>,
+}
+
+impl<'a, T, const N: usize> core::fmt::Debug for Producer<'a, T, {N}> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Producer").field("queue", self.queue).finish()
+    }
+}
+
+impl<'a, T, const N: usize> core::fmt::Debug for Consumer<'a, T, {N}> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Consumer").field("queue", self.queue).finish()
+    }
+}
+
+impl<'a, T, const N: usize> Producer<'a, T, {N}> {
+    pub fn push(&mut self, t: T) -> Result<(), T> {
+        match self.queue.start_push() {
+            None => Err(t),
+            Some(push) => {
+                self.queue.finish_push(push, t);
+                Ok(())
+            }
+        }
+    }
+
+    // Push, and call on_became_nonempty if this push took the queue from empty to non-empty
+    pub fn push_notify<F: FnOnce()>(&mut self, t: T, on_became_nonempty: F) -> Result<(), T> {
+        let push = match self.queue.start_push() {
+            None => return Err(t),
+            Some(push) => push,
+        };
+        self.queue.finish_push(push, t);
+
+        // Same as TicketQueue: the consumer has caught up with our item, so the queue was empty before it,
+        //   or it has taken it already, which makes the call redundant but harmless
+        fence(Ordering::SeqCst);
+        if self.queue.pop_index.0.load(Ordering::Acquire).wrapping_sub(push) as isize >= 0 {
+            on_became_nonempty();
+        }
+
+        Ok(())
+    }
+
+    // Refuse all pushes from now on. Only the producer can close, so no push can race with it,
+    //   and once the consumer sees it closed, every item pushed is visible to it.
+    pub fn close(&self) {
+        self.queue.closed.store(true, Ordering::Release);
+    }
+
+    pub fn queue(&self) -> &'a SpscQueue<T, {N}> {
+        self.queue
+    }
+}
+
+impl<'a, T, const N: usize> Consumer<'a, T, {N}> {
+    pub fn pop(&mut self) -> Option<T> {
+        self.queue.pop()
+    }
+
+    // Like pop, but tells "empty for now" apart from "closed and drained"
+    pub fn try_pop(&mut self) -> Pop<T> {
+        if let Some(t) = self.queue.pop() {
+            return Pop::Item(t);
+        }
+
+        if !self.queue.is_closed() {
+            return Pop::Empty;
+        }
+
+        // Something may have been pushed between the failed pop and the close
+        match self.queue.pop() {
+            Some(t) => Pop::Item(t),
+            None => Pop::Closed,
+        }
+    }
+
+    // The item can only go away through pop, which needs the consumer borrowed mutably
+    pub fn peek(&self) -> Option<&T> {
+        let pop = self.queue.pop_index.0.load(Ordering::Relaxed);
+        if self.queue.push_index.0.load(Ordering::Acquire) == pop {
+            return None;
+        }
+
+        Some(unsafe { &*self.queue.slot(pop).with(|ptr| (*ptr).as_ptr()) })
+    }
+
+    pub fn queue(&self) -> &'a SpscQueue<T, {N}> {
+        self.queue
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn push_pop() {
+        let mut queue: SpscQueue<usize, 3> = SpscQueue::new();
+        let (mut producer, mut consumer) = queue.split();
+        assert_eq!(consumer.pop(), None);
+
+        // Go round the ring a few times
+        for round in 0..4 {
+            for i in 0..3 {
+                producer.push(round * 3 + i).unwrap();
+            }
+            assert_eq!(producer.push(42), Err(42));
+            assert_eq!(producer.queue().len(), 3);
+            assert_eq!(consumer.peek(), Some(&(round * 3)));
+
+            for i in 0..3 {
+                assert_eq!(consumer.pop(), Some(round * 3 + i));
+            }
+            assert_eq!(consumer.pop(), None);
+        }
+
+        let mut notified = 0;
+        producer.push_notify(0, || notified += 1).unwrap();
+        producer.push_notify(1, || notified += 1).unwrap();
+        assert_eq!(notified, 1);
+
+        producer.close();
+        assert_eq!(producer.push(2), Err(2));
+        assert_eq!(consumer.try_pop(), Pop::Item(0));
+        assert_eq!(consumer.try_pop(), Pop::Item(1));
+        assert_eq!(consumer.try_pop(), Pop::Closed);
+    }
+
+    #[test]
+    fn split_again() {
+        let mut queue: SpscQueue<usize, 4> = Default::default();
+
+        let (mut producer, _) = queue.split();
+        producer.push(1).unwrap();
+        producer.push(2).unwrap();
+
+        let (_, mut consumer) = queue.split();
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn drops_leftovers() {
+        let item = Arc::new(());
+        {
+            let mut queue: SpscQueue<Arc<()>, 4> = SpscQueue::new();
+            let (mut producer, mut consumer) = queue.split();
+            for _ in 0..3 {
+                producer.push(item.clone()).unwrap();
+            }
+            drop(consumer.pop());
+            assert_eq!(Arc::strong_count(&item), 3);
+        }
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn spsc() {
+        const RANGE: core::ops::Range<usize> = 0usize..65536usize;
+
+        let queue: Box<SpscQueue<usize, 128>> = Box::new(SpscQueue::new());
+        let queue = Box::leak(queue);
+
+        let (mut producer, mut consumer) = queue.split();
+
+        let pth = std::thread::spawn(move || {
+            for i in RANGE {
+                while producer.push(i).is_err() {}
+            }
+        });
+
+        let cth = std::thread::spawn(move || {
+            for i in RANGE {
+                loop {
+                    match consumer.pop() {
+                        None => continue,
+                        Some(j) if j == i => break,
+                        Some(j) => panic!("Unexpected item {}. Was waiting for {}.", j, i),
+                    }
+                }
+            }
+            assert_eq!(consumer.pop(), None);
+        });
+
+        pth.join().unwrap();
+        cth.join().unwrap();
+    }
+}
@@ -0,0 +1,44 @@
+// Synchronization primitives used by the queue. With the loom feature they are swapped for loom's,
+//   which model-check every interleaving under the C11 memory model instead of whatever the host CPU does.
+// Everything touching the queue's shared state goes through here, so the two can't drift apart.
+
+#[cfg(not(feature = "loom"))]
+pub(crate) use core::sync::atomic::{fence, spin_loop_hint, AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+// loom's spin_loop_hint yields to the scheduler, so spin loops don't explode the state space
+#[cfg(feature = "loom")]
+pub(crate) use loom::sync::atomic::{fence, spin_loop_hint, AtomicBool, AtomicUsize, Ordering};
+
+// For waits that give the core away. Needs an OS thread to yield to.
+#[cfg(all(any(feature = "std", test), not(feature = "loom")))]
+pub(crate) use std::thread::yield_now;
+#[cfg(all(any(feature = "std", test), feature = "loom"))]
+pub(crate) use loom::thread::yield_now;
+
+#[cfg(all(feature = "stats", not(feature = "loom")))]
+pub(crate) use core::sync::atomic::AtomicU64;
+#[cfg(all(feature = "stats", feature = "loom"))]
+pub(crate) use loom::sync::atomic::AtomicU64;
+
+#[cfg(feature = "loom")]
+pub(crate) use loom::cell::UnsafeCell;
+
+// Same interface as loom's UnsafeCell: the pointer is only handed out inside a closure,
+//   which is where loom checks the access against concurrent ones
+#[cfg(not(feature = "loom"))]
+pub(crate) struct UnsafeCell<T>(core::cell::UnsafeCell<T>);
+
+#[cfg(not(feature = "loom"))]
+impl<T> UnsafeCell<T> {
+    pub fn new(data: T) -> Self {
+        Self(core::cell::UnsafeCell::new(data))
+    }
+
+    pub fn with<R, F: FnOnce(*const T) -> R>(&self, f: F) -> R {
+        f(self.0.get())
+    }
+
+    pub fn with_mut<R, F: FnOnce(*mut T) -> R>(&self, f: F) -> R {
+        f(self.0.get())
+    }
+}
@@ -0,0 +1,237 @@
+use super::hierarchical::{SlotLike, Wheel};
+
+use crate::sync::*;
+use alloc::vec::Vec;
+
+// A wheel shared by reference, e.g. scheduled into from several threads while one of them drives it.
+// Every call takes a spinlock around the wheel. fast_forward only holds it while taking out the due items,
+//   and runs the callbacks after letting go, so they can schedule into the wheel themselves.
+// Advancing is one thread's job at a time. A fast_forward starting while another one is still firing would fire items
+//   out of order. Racing in from a second thread, it returns false without firing anything, and may be tried again later.
+//   Reentrant from one of the callbacks it's a bug, so it panics instead. Without std the thread can't be told,
+//   so that returns false too.
+pub struct ConcurrentWheel<T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> {
+    wheel: UnsafeCell<Wheel<T, S, LEVEL, CUTOFF>>,
+    locked: AtomicBool,
+    // Set for the whole of a fast_forward, callbacks included
+    firing: AtomicBool,
+    // The thread firing, to tell a reentrant fast_forward apart from one racing in from another thread
+    #[cfg(any(feature = "std", test))]
+    driver: std::sync::Mutex<Option<std::thread::ThreadId>>,
+}
+
+// wheel is only touched with locked held
+unsafe impl<T: Send, S: SlotLike<Item = (T, usize)> + Send, const LEVEL: usize, const CUTOFF: usize> Sync for ConcurrentWheel<T, S, LEVEL, CUTOFF> {}
+
+impl<T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> ConcurrentWheel<T, S, LEVEL, CUTOFF> {
+    pub fn new(elapsed: usize) -> Self {
+        Self {
+            wheel: UnsafeCell::new(Wheel::new(elapsed)),
+            locked: AtomicBool::new(false),
+            firing: AtomicBool::new(false),
+            #[cfg(any(feature = "std", test))]
+            driver: std::sync::Mutex::new(None),
+        }
+    }
+
+    fn with_wheel<R, F: FnOnce(&mut Wheel<T, S, LEVEL, CUTOFF>) -> R>(&self, f: F) -> R {
+        while self.locked.compare_and_swap(false, true, Ordering::Acquire) {
+            spin_loop_hint();
+        }
+
+        // Released even if f panics, e.g. advancing into the past
+        let _unlock = Unlock(&self.locked);
+        self.wheel.with_mut(|wheel| f(unsafe { &mut *wheel }))
+    }
+
+    pub fn schedule(&self, tick: usize, i: T) -> Result<(), T> {
+        self.with_wheel(|wheel| wheel.schedule(tick, i))
+    }
+
+    pub fn elapsed(&self) -> usize {
+        self.with_wheel(|wheel| wheel.elapsed())
+    }
+
+    pub fn min_next_event(&self) -> Option<usize> {
+        self.with_wheel(|wheel| wheel.min_next_event())
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.with_wheel(|wheel| wheel.pending_count())
+    }
+
+    // Items a panicking callback didn't get to go back into the wheel, and fire on the next advance.
+    // false if another thread is still firing, nothing is fired then, see the top of this file.
+    pub fn fast_forward<F: FnMut(T, usize)>(&self, moment: usize, mut f: F) -> bool {
+        if self.firing.swap(true, Ordering::Acquire) {
+            #[cfg(any(feature = "std", test))]
+            {
+                // Let go of the lock first, panicking with it held would poison it
+                let reentrant = *self.driver.lock().unwrap() == Some(std::thread::current().id());
+                if reentrant {
+                    panic!("ConcurrentWheel::fast_forward called from one of its callbacks while it is still firing");
+                }
+            }
+            return false;
+        }
+
+        #[cfg(any(feature = "std", test))]
+        {
+            *self.driver.lock().unwrap() = Some(std::thread::current().id());
+        }
+
+        let mut firing = Firing {
+            wheel: self,
+            due: Vec::new().into_iter(),
+        };
+        firing.due = self.with_wheel(|wheel| wheel.advance_to(moment).collect::<Vec<_>>()).into_iter();
+
+        for (item, ts) in &mut firing.due {
+            f(item, ts);
+        }
+        true
+    }
+}
+
+struct Unlock<'a>(&'a AtomicBool);
+
+impl<'a> Drop for Unlock<'a> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+// Ends a fast_forward, also when a callback unwinds through it
+struct Firing<'a, T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> {
+    wheel: &'a ConcurrentWheel<T, S, LEVEL, CUTOFF>,
+    due: alloc::vec::IntoIter<(T, usize)>,
+}
+
+impl<'a, T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> Drop for Firing<'a, T, S, LEVEL, CUTOFF> {
+    fn drop(&mut self) {
+        if self.due.len() != 0 {
+            let due = &mut self.due;
+            self.wheel.with_wheel(|wheel| wheel.park(due));
+        }
+
+        #[cfg(any(feature = "std", test))]
+        {
+            *self.wheel.driver.lock().unwrap() = None;
+        }
+        self.wheel.firing.store(false, Ordering::Release);
+    }
+}
+
+impl<T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> core::fmt::Debug for ConcurrentWheel<T, S, LEVEL, CUTOFF> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ConcurrentWheel")
+            .field("elapsed", &self.elapsed())
+            .field("firing", &self.firing.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    type Wheel = super::ConcurrentWheel<usize, VecDeque<(usize, usize)>, 8, 6>;
+
+    #[test]
+    fn schedule_from_callback() {
+        let wheel = Wheel::new(0);
+        wheel.schedule(5, 0).unwrap();
+
+        // Rescheduling itself doesn't deadlock, the lock is let go of before the callbacks run
+        let mut fired = Vec::new();
+        wheel.fast_forward(10, |item, at| {
+            fired.push((item, at));
+            wheel.schedule(at + 10, item + 1).unwrap();
+        });
+        assert_eq!(fired, vec![(0, 5)]);
+
+        wheel.fast_forward(20, |item, at| fired.push((item, at)));
+        assert_eq!(fired[1..], [(1, 15)]);
+        assert_eq!(wheel.min_next_event(), None);
+    }
+
+    #[test]
+    fn reentrant_fast_forward() {
+        let wheel = Wheel::new(0);
+        for tick in 1..4 {
+            wheel.schedule(tick, tick).unwrap();
+        }
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            wheel.fast_forward(10, |_, _| {
+                wheel.fast_forward(20, |_, _| ());
+            });
+        }));
+        let message = *result.unwrap_err().downcast::<&str>().unwrap();
+        assert!(message.contains("still firing"));
+
+        // The items the callback didn't get to are back, and the wheel works as before
+        assert_eq!(wheel.pending_count(), 2);
+        let mut fired = Vec::new();
+        wheel.fast_forward(20, |item, _| fired.push(item));
+        assert_eq!(fired, vec![2, 3]);
+    }
+
+    #[test]
+    fn second_driver() {
+        use std::sync::{Arc, Barrier};
+
+        let wheel = Arc::new(Wheel::new(0));
+        wheel.schedule(5, 0).unwrap();
+        wheel.schedule(15, 1).unwrap();
+
+        // Held up in its callback until the other thread has tried to advance too
+        let started = Arc::new(Barrier::new(2));
+        let tried = Arc::new(Barrier::new(2));
+        let driver = {
+            let (wheel, started, tried) = (wheel.clone(), started.clone(), tried.clone());
+            std::thread::spawn(move || {
+                let mut fired = Vec::new();
+                assert!(wheel.fast_forward(10, |item, at| {
+                    started.wait();
+                    tried.wait();
+                    fired.push((item, at));
+                }));
+                fired
+            })
+        };
+
+        // Not a panic, only told to come back later
+        started.wait();
+        assert!(!wheel.fast_forward(20, |_, _| panic!()));
+        tried.wait();
+        assert_eq!(driver.join().unwrap(), vec![(0, 5)]);
+
+        let mut fired = Vec::new();
+        assert!(wheel.fast_forward(20, |item, at| fired.push((item, at))));
+        assert_eq!(fired, vec![(1, 15)]);
+    }
+
+    #[test]
+    fn shared() {
+        let wheel = std::sync::Arc::new(Wheel::new(0));
+        let mut fired = 0;
+
+        let threads: Vec<_> = (0..2).map(|t| {
+            let wheel = wheel.clone();
+            std::thread::spawn(move || {
+                for i in 0..100 {
+                    wheel.schedule(1000 + i, t).unwrap();
+                }
+            })
+        }).collect();
+        wheel.fast_forward(500, |_, _| fired += 1);
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        wheel.fast_forward(2000, |_, _| fired += 1);
+        assert_eq!(fired, 200);
+    }
+}
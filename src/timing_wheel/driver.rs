@@ -0,0 +1,101 @@
+use super::hierarchical::{SlotLike, Wheel};
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// The ISR loop around a wheel: a hardware timer bumps a tick counter, the interrupt handler polls the driver,
+//   then programs the next interrupt from next_deadline.
+// Needs nothing but core, so it works in no_std kernels.
+pub struct WheelDriver<'a, T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> {
+    wheel: Wheel<T, S, LEVEL, CUTOFF>,
+    ticks: &'a AtomicUsize,
+}
+
+impl<'a, T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> WheelDriver<'a, T, S, LEVEL, CUTOFF> {
+    // The wheel's elapsed is taken as the last tick seen, the counter may already be ahead of it
+    pub fn new(wheel: Wheel<T, S, LEVEL, CUTOFF>, ticks: &'a AtomicUsize) -> Self {
+        Self { wheel, ticks }
+    }
+
+    pub fn wheel(&self) -> &Wheel<T, S, LEVEL, CUTOFF> {
+        &self.wheel
+    }
+
+    // For scheduling and cancelling. Advancing it by hand is fine too, poll picks up from wherever it is.
+    pub fn wheel_mut(&mut self) -> &mut Wheel<T, S, LEVEL, CUTOFF> {
+        &mut self.wheel
+    }
+
+    pub fn into_inner(self) -> Wheel<T, S, LEVEL, CUTOFF> {
+        self.wheel
+    }
+
+    // Catch the wheel up with the tick counter, firing everything due on the way.
+    // Spurious interrupts are cheap: nothing happens unless the counter moved,
+    //   and a move with nothing due only updates elapsed. Returns whether the counter moved.
+    pub fn poll<F: FnMut(T, usize)>(&mut self, f: F) -> bool {
+        let now = self.ticks.load(Ordering::Acquire);
+        if now <= self.wheel.elapsed() {
+            return false;
+        }
+
+        self.wheel.advance_if_due(now, f);
+        true
+    }
+
+    // The tick to program the timer for, None if nothing is scheduled and the timer can be switched off.
+    // Items due at the current tick only fire once the counter moves, so the deadline is never before the next tick.
+    // For items on an upper level, it's the start of their bucket. Polling there only cascades them,
+    //   and the deadline after that is the precise one.
+    pub fn next_deadline(&self) -> Option<usize> {
+        self.wheel.min_next_event().map(|next| core::cmp::max(next, self.wheel.elapsed() + 1))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::timing_wheel::hierarchical::BoundedWheel;
+
+    #[test]
+    fn poll() {
+        let clock = AtomicUsize::new(0);
+        let mut driver = WheelDriver::new(BoundedWheel::<usize, 4>::new(0), &clock);
+        assert_eq!(driver.next_deadline(), None);
+
+        driver.wheel_mut().schedule(0, 0).unwrap();
+        driver.wheel_mut().schedule(3, 1).unwrap();
+        driver.wheel_mut().schedule(100, 2).unwrap();
+
+        // Scheduled at the current tick, fires on the next one
+        assert_eq!(driver.next_deadline(), Some(1));
+        assert!(!driver.poll(|_, _| panic!()));
+
+        let mut fired = Vec::new();
+        clock.store(1, Ordering::Release);
+        assert!(driver.poll(|item, at| fired.push((item, at))));
+        assert_eq!(fired, [(0, 0)]);
+        assert_eq!(driver.next_deadline(), Some(3));
+
+        // Spurious interrupt, the counter hasn't moved
+        assert!(!driver.poll(|_, _| panic!()));
+
+        // Woken late, everything missed fires at once
+        clock.store(5, Ordering::Release);
+        driver.poll(|item, at| fired.push((item, at)));
+        assert_eq!(fired, [(0, 0), (1, 3)]);
+
+        // The level 1 bucket of 100 first, then the tick itself once it's cascaded
+        assert_eq!(driver.next_deadline(), Some(64));
+        clock.store(64, Ordering::Release);
+        driver.poll(|_, _| panic!());
+        assert_eq!(driver.next_deadline(), Some(100));
+
+        // Simulated timer: jump to each deadline until nothing is left
+        while let Some(deadline) = driver.next_deadline() {
+            clock.store(deadline, Ordering::Release);
+            driver.poll(|item, at| fired.push((item, at)));
+        }
+        assert_eq!(fired, [(0, 0), (1, 3), (2, 100)]);
+        assert_eq!(driver.into_inner().elapsed(), 100);
+    }
+}
@@ -1,16 +1,37 @@
+use crate::util::init_array;
+
 use core::mem::MaybeUninit;
 
-// TODO: impl cancel
+// The order a slot hands back items that went in one after another, which is the order same-tick events fire in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotOrder {
+    // Oldest first
+    Fifo,
+    // Newest first
+    Lifo,
+    // Decided by something other than insertion order, e.g. PrioritySlot, or not at all
+    Unspecified,
+}
 
 pub trait SlotLike : Default {
     type Item;
 
+    // What pop promises. The wheel keeps that order across cascades, so it holds for whole ticks.
+    const ORDER: SlotOrder = SlotOrder::Unspecified;
+
     fn push(&mut self, i: Self::Item) -> Result<(), Self::Item>;
     fn pop(&mut self) -> Option<Self::Item>;
     fn size(&self) -> usize;
+    // Visit items in place. Slots with an ORDER visit them in the order they were pushed,
+    //   so pushing them into an empty slot in that order rebuilds it, which is how a saved wheel is restored.
+    fn for_each_item<'a, F: FnMut(&'a Self::Item)>(&'a self, f: F);
+    // How many items fit, None if it grows as needed
+    fn capacity(&self) -> Option<usize> {
+        None
+    }
 }
 
-// Asserts that N < 64
+// N is at most 64, checked at compile time
 struct Level<S: SlotLike, const N: usize> {
     bitset: u64,
     slots: [S; N],
@@ -18,19 +39,19 @@ struct Level<S: SlotLike, const N: usize> {
 
 impl<S: SlotLike, const N: usize> Default for Level<S, N> {
     fn default() -> Self {
-        let mut slots: [S; N] = unsafe { MaybeUninit::uninit().assume_init() };
-        for slot in slots.iter_mut() {
-            unsafe { core::ptr::write(slot, Default::default()) };
-        }
+        let _ = Self::FITS_BITSET;
 
         Self {
             bitset: 0,
-            slots,
+            slots: init_array(Default::default),
         }
     }
 }
 
 impl<S: SlotLike, const N: usize> Level<S, N> {
+    // Fails the build when a level with too many slots is instantiated
+    const FITS_BITSET: () = assert!(N <= 64, "A level has at most 64 slots, they are tracked in a u64 bitset");
+
     pub fn push_at(&mut self, at: u32, i: S::Item) -> Result<(), S::Item> {
         let ret = self.slots[at as usize].push(i);
         if ret.is_ok() {
@@ -39,6 +60,24 @@ impl<S: SlotLike, const N: usize> Level<S, N> {
         ret
     }
 
+    // Push a run of items into one slot, setting its bit once.
+    // Items the slot refuses, or that would take it to more than cap items, are handed to reject.
+    #[cfg(any(feature = "alloc", test))]
+    pub fn push_run_at<I: Iterator<Item = S::Item>, R: FnMut(S::Item)>(&mut self, at: u32, run: I, cap: usize, mut reject: R) {
+        let slot = &mut self.slots[at as usize];
+        for i in run {
+            if slot.size() >= cap {
+                reject(i);
+            } else if let Err(i) = slot.push(i) {
+                reject(i);
+            }
+        }
+
+        if slot.size() != 0 {
+            self.bitset |= 1 << at;
+        }
+    }
+
     pub fn pop_at(&mut self, at: u32) -> Option<S::Item> {
         let popped = self.slots[at as usize].pop();
         if self.slots[at as usize].size() == 0 {
@@ -57,8 +96,57 @@ impl<S: SlotLike, const N: usize> Level<S, N> {
         }
     }
 
+    pub fn slot_size(&self, at: u32) -> usize {
+        self.slots[at as usize].size()
+    }
+
+    // Indices of the non-empty slots, ascending
+    pub fn occupied(&self) -> impl Iterator<Item = u32> {
+        let mut bitset = self.bitset;
+        core::iter::from_fn(move || {
+            if bitset == 0 {
+                return None;
+            }
+
+            let idx = bitset.trailing_zeros();
+            bitset &= !(1 << idx);
+            Some(idx)
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.occupied().map(|idx| self.slots[idx as usize].size()).sum()
+    }
+
+    // Items over what all slots can hold for bounded slots, occupied slots over all slots otherwise
+    pub fn fill_ratio(&self) -> f32 {
+        match self.slots[0].capacity() {
+            Some(cap) => self.len() as f32 / (N * cap) as f32,
+            None => self.bitset.count_ones() as f32 / N as f32,
+        }
+    }
+
+    #[cfg(any(feature = "alloc", test))]
+    pub fn for_each_item<'a, F: FnMut(&'a S::Item)>(&'a self, mut f: F) {
+        for idx in self.occupied() {
+            self.slots[idx as usize].for_each_item(&mut f);
+        }
+    }
+
+    pub fn remove_at<F: FnMut(&S::Item) -> bool>(&mut self, at: u32, f: F) -> Option<S::Item> {
+        let removed = remove_from_slot(&mut self.slots[at as usize], f);
+        if self.slots[at as usize].size() == 0 {
+            self.bitset &= !(1 << at);
+        }
+        removed
+    }
+
     pub fn replace_slot(&mut self, idx: u32, slot: S) -> S {
-        self.bitset &= !(1 << idx);
+        if slot.size() == 0 {
+            self.bitset &= !(1 << idx);
+        } else {
+            self.bitset |= 1 << idx;
+        }
         core::mem::replace(&mut self.slots[idx as usize], slot)
     }
 
@@ -74,7 +162,29 @@ impl<S: SlotLike, const N: usize> Level<S, N> {
     }
 }
 
-struct LevelDrain<'a, S: SlotLike, const N: usize> {
+// Take out the first item f matches, keeping the others in order. Slots can only pop, so the whole slot
+//   goes out and back in through a second one, which restores the order for FIFO and LIFO slots alike.
+fn remove_from_slot<S: SlotLike, F: FnMut(&S::Item) -> bool>(slot: &mut S, mut f: F) -> Option<S::Item> {
+    let mut rest = S::default();
+    let mut removed = None;
+    while let Some(i) = slot.pop() {
+        if removed.is_none() && f(&i) {
+            removed = Some(i);
+        } else if rest.push(i).is_err() {
+            unreachable!("A slot of the same type holds as much as the one the items came from");
+        }
+    }
+
+    while let Some(i) = rest.pop() {
+        if slot.push(i).is_err() {
+            unreachable!("The slot held all of these a moment ago");
+        }
+    }
+    removed
+}
+
+// Pops a level's slots in index order, clearing their bits as they empty. See Wheel::drain_due.
+pub struct LevelDrain<'a, S: SlotLike, const N: usize> {
     level: &'a mut Level<S, N>,
     until: u32,
 }
@@ -96,39 +206,299 @@ impl<'a, S: SlotLike, const N: usize> Iterator for LevelDrain<'a, S, N> {
     }
 }
 
-// CUTOFF should be less than 6 (or 64)
-// LEVEL is recommended to be ceil(64 / CUTOFF)
+// A hierarchical timing wheel with LEVEL levels of 1 << CUTOFF slots each, backed by any SlotLike holding (item, tick).
+// LEVEL has to be at least 1, ceil(64 / CUTOFF) covers every tick. With fewer, schedule rejects ticks past horizon()
+//   and schedule_far parks them in an overflow slot until they come into range.
+// SlotWheel is the same with the default geometry of the other wheels.
+/// CUTOFF is at most 6, so a level fits into a u64 bitset:
+///
+/// ```compile_fail,E0080
+/// use queueue::timing_wheel::hierarchical::{Wheel, BoundedSlot};
+///
+/// let wheel = Wheel::<usize, BoundedSlot<(usize, usize), 4>, 5, 10>::new(0);
+/// ```
+///
+/// ```
+/// use queueue::timing_wheel::{SlotLike, SlotOrder, Wheel};
+///
+/// // Fires same-tick items newest first
+/// struct StackSlot<T>(Vec<T>);
+///
+/// impl<T> Default for StackSlot<T> {
+///     fn default() -> Self {
+///         StackSlot(Vec::new())
+///     }
+/// }
+///
+/// impl<T> SlotLike for StackSlot<T> {
+///     type Item = T;
+///     const ORDER: SlotOrder = SlotOrder::Lifo;
+///
+///     fn push(&mut self, i: T) -> Result<(), T> {
+///         self.0.push(i);
+///         Ok(())
+///     }
+///
+///     fn pop(&mut self) -> Option<T> {
+///         self.0.pop()
+///     }
+///
+///     fn size(&self) -> usize {
+///         self.0.len()
+///     }
+///
+///     fn for_each_item<'a, F: FnMut(&'a T)>(&'a self, f: F) {
+///         self.0.iter().for_each(f);
+///     }
+/// }
+///
+/// // 3 levels of 16 slots, so ticks up to 2^12 - 1 ahead of elapsed
+/// let mut wheel = Wheel::<&str, StackSlot<(&str, usize)>, 3, 4>::new(0);
+/// wheel.schedule(300, "hello").unwrap();
+/// assert!(wheel.schedule(1 << 12, "too far").is_err());
+///
+/// let mut fired = Vec::new();
+/// wheel.fast_forward(300, |item, at| fired.push((item, at)));
+/// assert_eq!(fired, [("hello", 300)]);
+/// ```
 pub struct Wheel<T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> {
     elapsed: usize,
     levels: [WheelLevel<S, CUTOFF>; LEVEL],
+    max_slot_occupancy: usize,
+    // The slot drained by the last cascade, swapped in for the next one instead of a fresh default.
+    // Saves an allocation per cascade for allocating slots, at the cost of one extra slot of memory.
+    spare: Option<S>,
+    // Items beyond horizon(), waiting for the top level to roll over. None of them can come into range
+    //   before that, so they are only looked at when advance_to crosses into a new top-level rotation.
+    overflow: Option<S>,
+    overflow_min: usize,
+    // Expired items an interrupted advance didn't get to yield, see advance_to. Newest first, so the oldest pops off the end.
+    // Without alloc they go into the slot of the current tick instead, or stay where they are.
+    #[cfg(any(feature = "alloc", test))]
+    parked: alloc::vec::Vec<(T, usize)>,
+    // Without alloc, where an interrupted advance that left its leftovers where they are was headed, see advance_to.
+    //   elapsed stays where that advance started, which is what the levels are still placed for, until the next one.
+    #[cfg(not(feature = "alloc"))]
+    interrupted: Option<usize>,
 }
 
 impl<T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> Wheel<T, S, LEVEL, CUTOFF> {
+    const CUTOFF_FITS: () = assert!(CUTOFF <= 6, "CUTOFF is at most 6, each level's slots are tracked in a u64 bitset");
+
     pub fn new(elapsed: usize) -> Self {
-        let mut levels: [WheelLevel<S, CUTOFF>; LEVEL] = unsafe { MaybeUninit::uninit().assume_init() };
-        for level in levels.iter_mut() {
-            unsafe { core::ptr::write(level, Default::default()) };
-        }
+        let _ = Self::CUTOFF_FITS;
 
         Self {
             elapsed,
-            levels,
+            levels: init_array(Default::default),
+            max_slot_occupancy: usize::MAX,
+            spare: None,
+            overflow: None,
+            overflow_min: usize::MAX,
+            #[cfg(any(feature = "alloc", test))]
+            parked: alloc::vec::Vec::new(),
+            #[cfg(not(feature = "alloc"))]
+            interrupted: None,
         }
     }
+
+    // Rebuild a wheel from (tick, item) pairs, e.g. as listed by sorted_entries. Items sharing a slot go in in the order given,
+    //   so that's the order they fire in for FIFO slots, and the reverse for LIFO ones.
+    // Ticks past the horizon go to the overflow. Err with the first pair that can't be placed,
+    //   because its tick is before elapsed or its slot is full.
+    pub fn from_events<I: IntoIterator<Item = (usize, T)>>(elapsed: usize, events: I) -> Result<Self, (usize, T)> {
+        let mut wheel = Self::new(elapsed);
+        for (tick, item) in events {
+            wheel.schedule_far(tick, item).map_err(|item| (tick, item))?;
+        }
+
+        Ok(wheel)
+    }
+
+    // Reject schedules into slots already holding `cap` items, which bounds the work a single slot
+    //   costs when it fires. Cascading may still move items into a full slot, as they can't be rejected there.
+    pub fn with_max_slot_occupancy(mut self, cap: usize) -> Self {
+        self.max_slot_occupancy = cap;
+        self
+    }
+
     pub fn schedule(&mut self, tick: usize, i: T) -> Result<(), T> {
+        if tick < self.elapsed() {
+            return Err(i);
+        }
+
         let (wheel, offset) = if let Some(inner) = self.get_pos(tick) {
             inner
         } else {
             return Err(i);
         };
 
+        if self.levels[wheel].slot_size(offset) >= self.max_slot_occupancy {
+            return Err(i);
+        }
+
         self.levels[wheel].push_at(offset, (i, tick)).map_err(|err| err.0)
     }
 
-    fn get_pos(&mut self, tick: usize) -> Option<(usize, u32)> {
-        assert!(tick < (1 << (CUTOFF * LEVEL)));
+    // Schedule a batch, grouped by slot so each slot is looked up and marked once.
+    // Items sharing a slot go in in the order given. Unlike a loop over schedule, failures don't stop the batch:
+    //   Err holds every (tick, item) that wasn't scheduled, past, beyond the horizon or into a full slot, in the order given.
+    #[cfg(any(feature = "alloc", test))]
+    pub fn schedule_many<I: IntoIterator<Item = (usize, T)>>(&mut self, items: I) -> Result<(), alloc::vec::Vec<(usize, T)>> {
+        let mut placed = alloc::vec::Vec::new();
+        let mut rejected = alloc::vec::Vec::new();
+
+        let now = self.elapsed();
+        for (idx, (tick, item)) in items.into_iter().enumerate() {
+            match self.get_pos(tick) {
+                Some((wheel, offset)) if tick >= now => placed.push((wheel, offset, idx, tick, item)),
+                _ => rejected.push((idx, tick, item)),
+            }
+        }
+
+        // Stable, so the order within a slot is kept
+        placed.sort_by_key(|(wheel, offset, _, _, _)| (*wheel, *offset));
+
+        let cap = self.max_slot_occupancy;
+        let mut placed = placed.into_iter().peekable();
+        while let Some(&(wheel, offset, _, _, _)) = placed.peek() {
+            // push_run_at rejects an item before pulling the next one, so the last index pulled is the rejected one's
+            let current = core::cell::Cell::new(0);
+            let placed = &mut placed;
+            let run = core::iter::from_fn(|| match placed.peek() {
+                Some(&(w, o, _, _, _)) if w == wheel && o == offset => placed.next().map(|(_, _, idx, tick, item)| {
+                    current.set(idx);
+                    (item, tick)
+                }),
+                _ => None,
+            });
+
+            self.levels[wheel].push_run_at(offset, run, cap, |(item, tick)| rejected.push((current.get(), tick, item)));
+        }
 
-        if tick < self.elapsed {
+        if rejected.is_empty() {
+            return Ok(());
+        }
+
+        rejected.sort_by_key(|(idx, _, _)| *idx);
+        Err(rejected.into_iter().map(|(_, tick, item)| (tick, item)).collect())
+    }
+
+    // Put back a cascaded item that isn't due yet
+    fn cascade_back(&mut self, tick: usize, i: T) {
+        if tick > self.horizon() {
+            // Only when cascading the overflow slot. It came out of there, so there's room to put it back.
+            let _ = self.push_overflow(tick, i);
+        } else {
+            let _ = self.insert(tick, i);
+        }
+    }
+
+    // Schedule without the occupancy cap, for items already accepted once
+    fn insert(&mut self, tick: usize, i: T) -> Result<(), T> {
+        let (wheel, offset) = if let Some(inner) = self.get_pos(tick) {
+            inner
+        } else {
+            return Err(i);
+        };
+
+        self.levels[wheel].push_at(offset, (i, tick)).map_err(|err| err.0)
+    }
+
+    // schedule accepts tick == elapsed, which only fires on the next advance.
+    // This one rejects it along with the past, to catch off-by-one mistakes.
+    pub fn schedule_strict(&mut self, tick: usize, i: T) -> Result<(), T> {
+        if tick <= self.elapsed() {
+            return Err(i);
+        }
+
+        self.schedule(tick, i)
+    }
+
+    // Schedule relative to elapsed. A zero delay is rejected, because the current tick
+    //   has already been fast-forwarded past, so it would only fire on the next advance.
+    pub fn schedule_after(&mut self, delay: usize, i: T) -> Result<(), T> {
+        if delay == 0 {
+            return Err(i);
+        }
+
+        match self.elapsed().checked_add(delay) {
+            Some(tick) => self.schedule(tick, i),
+            None => Err(i),
+        }
+    }
+
+    // Like schedule, but ticks beyond horizon() are set aside in an overflow slot instead of rejected,
+    //   and moved into the wheel once it gets close enough. Err if the tick is in the past, or the overflow slot is full.
+    // The occupancy cap doesn't apply to the overflow slot.
+    pub fn schedule_far(&mut self, tick: usize, i: T) -> Result<(), T> {
+        if tick <= self.horizon() {
+            return self.schedule(tick, i);
+        }
+
+        self.push_overflow(tick, i)
+    }
+
+    fn push_overflow(&mut self, tick: usize, i: T) -> Result<(), T> {
+        if self.overflow.is_none() {
+            self.overflow = Some(self.spare.take().unwrap_or_default());
+        }
+
+        self.overflow.as_mut().unwrap().push((i, tick)).map_err(|err| err.0)?;
+        self.overflow_min = core::cmp::min(self.overflow_min, tick);
+        Ok(())
+    }
+
+    // The last tick schedule accepts right now. The levels only cover the low CUTOFF * LEVEL bits,
+    //   the rest has to match elapsed. So the horizon moves forward whenever the top level rolls over.
+    pub fn horizon(&self) -> usize {
+        const BITCOUNT_TOT: usize = core::mem::size_of::<usize>() * 8;
+        if CUTOFF * LEVEL >= BITCOUNT_TOT {
+            return usize::MAX;
+        }
+
+        self.elapsed | ((1 << (CUTOFF * LEVEL)) - 1)
+    }
+
+    // Take out the first item scheduled at tick that f matches, wherever it is by now. None if there is none.
+    // Linear in the size of the one slot it can be in.
+    // Without alloc, items an interrupted advance parked aren't found, see advance_to.
+    pub fn remove<F: FnMut(&T) -> bool>(&mut self, tick: usize, mut f: F) -> Option<T> {
+        let mut matches = |(item, at): &(T, usize)| *at == tick && f(item);
+
+        #[cfg(any(feature = "alloc", test))]
+        {
+            if let Some(pos) = self.parked.iter().position(&mut matches) {
+                return Some(self.parked.remove(pos).0);
+            }
+        }
+
+        if tick > self.horizon() {
+            let overflow = self.overflow.as_mut()?;
+            let removed = remove_from_slot(overflow, &mut matches)?;
+            self.update_overflow_min();
+            return Some(removed.0);
+        }
+
+        let (level, offset) = self.get_pos(tick)?;
+        self.levels[level].remove_at(offset, &mut matches).map(|(item, _)| item)
+    }
+
+    fn update_overflow_min(&mut self) {
+        let mut overflow_min = usize::MAX;
+        if let Some(overflow) = &self.overflow {
+            overflow.for_each_item(|(_, at)| overflow_min = core::cmp::min(overflow_min, *at));
+        }
+        self.overflow_min = overflow_min;
+    }
+
+    // Ticks left until `tick`, 0 if it's already reached
+    pub fn remaining(&self, tick: usize) -> usize {
+        tick.saturating_sub(self.elapsed())
+    }
+
+    fn get_pos(&mut self, tick: usize) -> Option<(usize, u32)> {
+        if tick < self.elapsed || tick > self.horizon() {
             return None;
         }
 
@@ -146,173 +516,1121 @@ impl<T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize>
         Some((wheel, offset as u32))
     }
 
+    // Without alloc, the levels may still be placed for an earlier tick, see advance_to
     pub fn elapsed(&self) -> usize {
+        #[cfg(not(feature = "alloc"))]
+        {
+            if let Some(moment) = self.interrupted {
+                return moment;
+            }
+        }
+
         self.elapsed
     }
 
-    pub fn fast_forward<F: FnMut(T, usize)>(&mut self, moment: usize, mut f: F) {
-        assert!(moment > self.elapsed);
+    // The order items due at the same tick fire in, relative to the order they were scheduled in
+    pub fn slot_order(&self) -> SlotOrder {
+        S::ORDER
+    }
 
-        if self.elapsed == moment {
-            return;
+    // Every scheduled (item, tick), including the overflowed and parked ones, in no particular order
+    #[cfg(any(feature = "alloc", test))]
+    fn for_each_entry<'a, F: FnMut(&'a (T, usize))>(&'a self, mut f: F) {
+        self.for_each_slotted(&mut f);
+        self.parked.iter().for_each(f);
+    }
+
+    // Same, leaving out the parked ones, slot by slot
+    #[cfg(any(feature = "alloc", test))]
+    fn for_each_slotted<'a, F: FnMut(&'a (T, usize))>(&'a self, mut f: F) {
+        for level in self.levels.iter() {
+            level.for_each_item(&mut f);
         }
 
-        let same_leading = (self.elapsed ^ moment).leading_zeros();
+        if let Some(overflow) = &self.overflow {
+            overflow.for_each_item(&mut f);
+        }
+    }
+
+    // The slots that may hold items due in [from, to), along with whether the slot's whole tick range lies in there.
+    // A level i slot covers the 2^(i * CUTOFF) ticks sharing elapsed's bits above level i and its index on level i.
+    // Without alloc, the current level 0 slot also holds whatever an interrupted advance parked, so it's always walked.
+    // The overflow spans everything beyond the horizon, only its items can tell.
+    fn slots_between<'a, F: FnMut(&'a S, bool)>(&'a self, from: usize, to: usize, mut f: F) {
         const BITCOUNT_TOT: usize = core::mem::size_of::<usize>() * 8;
-        let first_one = BITCOUNT_TOT - same_leading as usize - 1;
-        let first_same_wheel = first_one / CUTOFF;
+        if from >= to {
+            return;
+        }
 
-        #[cfg(test)]
-        println!("CASCADE: {} => {}, level {}", self.elapsed, moment, first_same_wheel);
+        for (i, level) in self.levels.iter().enumerate() {
+            let shift = i * CUTOFF;
+            let above = shift + CUTOFF;
+            let base = if above >= BITCOUNT_TOT { 0 } else { (self.elapsed >> above) << above };
 
-        // Cascade from first_one
-        // Clear all bottom queues
-        for i in 0..first_same_wheel {
-            // Draining in place is faster than replacing
-            for item in self.levels[i].drain() {
-                f(item.0, item.1);
-            }
-        }
+            for idx in level.occupied() {
+                let start = base | ((idx as usize) << shift);
+                let end = start.saturating_add(1 << shift);
 
-        // Clear skipped slots
-        let idx_mask = (1 << CUTOFF) - 1;
-        let from_idx = (self.elapsed >> (CUTOFF * first_same_wheel)) & idx_mask;
-        let to_idx = (moment >> (CUTOFF * first_same_wheel)) & idx_mask;
+                // Parked items are due before the slot's own tick, so its range says nothing about them
+                if i == 0 && start == self.elapsed {
+                    f(&level.slots[idx as usize], false);
+                    continue;
+                }
 
-        #[cfg(test)]
-        println!("| {} -> {}", from_idx, to_idx);
+                if end <= from || start >= to {
+                    continue;
+                }
 
-        if to_idx != from_idx {
-            // Implies to_idx > 0
-            for item in self.levels[first_same_wheel].drain_until(to_idx as u32) { // Upperbound
-                f(item.0, item.1)
+                f(&level.slots[idx as usize], start >= from && end <= to);
             }
         }
 
-        self.elapsed = moment;
+        if let Some(overflow) = &self.overflow {
+            f(overflow, false);
+        }
+    }
 
-        let mut cascading = self.levels[first_same_wheel].replace_slot(to_idx as u32, Default::default());
-        while let Some((item, ts)) = cascading.pop() {
-            if ts <= moment {
-                f(item, ts);
-            } else {
-                self.schedule(ts, item);
+    // How many items are due in [from, to), without touching the wheel.
+    // Slots lying entirely inside the window are counted by size, only the ones straddling its ends are walked.
+    pub fn count_between(&self, from: usize, to: usize) -> usize {
+        let mut count = 0;
+        self.slots_between(from, to, |slot, whole| {
+            if whole {
+                count += slot.size();
+                return;
             }
+
+            slot.for_each_item(|(_, tick)| {
+                if from <= *tick && *tick < to {
+                    count += 1;
+                }
+            })
+        });
+
+        #[cfg(any(feature = "alloc", test))]
+        {
+            count += self.parked.iter().filter(|(_, tick)| from <= *tick && *tick < to).count();
         }
+        count
     }
 
-    // Get the minimal possible time for the next event
-    pub fn min_next_event(&self) -> Option<usize> {
-        let mut left = self.elapsed;
-
-        for i in 0..LEVEL {
-            let tail = (left & ((1<<CUTOFF) - 1)) as u32;
-            left >>= CUTOFF;
+    // Every (item, tick) due in [from, to), in no particular order, without touching the wheel
+    pub fn for_each_between<'a, F: FnMut(&'a T, usize)>(&'a self, from: usize, to: usize, mut f: F) {
+        // Items of a whole slot are all in range, checking them anyway keeps this simple
+        self.slots_between(from, to, |slot, _| {
+            slot.for_each_item(|(item, tick)| {
+                if from <= *tick && *tick < to {
+                    f(item, *tick);
+                }
+            })
+        });
 
-            if let Some(ev) = self.levels[i].next_event(tail) {
-                let ret_high = (left << CUTOFF) | ev as usize;
-                return Some(ret_high << (i * CUTOFF));
+        #[cfg(any(feature = "alloc", test))]
+        {
+            for (item, tick) in self.parked.iter() {
+                if from <= *tick && *tick < to {
+                    f(item, *tick);
+                }
             }
         }
-        None
     }
-}
 
-pub struct BoundedSlot<T, const N: usize> {
-    storage: [MaybeUninit<T>; {N}],
-    size: usize,
-}
+    // Sorted exact ticks holding at least one item. Unlike min_next_event, upper levels are resolved
+    //   down to the tick stored with each item, so this walks every item.
+    #[cfg(any(feature = "alloc", test))]
+    pub fn occupied_ticks(&self) -> alloc::vec::Vec<usize> {
+        let mut ticks = alloc::vec::Vec::new();
+        self.for_each_entry(|(_, tick)| ticks.push(*tick));
 
-impl<T, const N: usize> Default for BoundedSlot<T, N> {
-    fn default() -> Self {
-        Self::new()
+        ticks.sort_unstable();
+        ticks.dedup();
+        ticks
     }
-}
 
-impl<T, const N: usize> BoundedSlot<T, {N}> {
-    pub const fn new() -> Self {
-        Self {
-            storage: [MaybeUninit::UNINIT; {N}],
-            size: 0,
-        }
+    // Every pending (tick, item), sorted by tick, without touching the wheel.
+    // All items due at the same tick always sit in the same level, so the stable sort keeps them
+    //   in the order they went into the slot. That's the order they fire in for FIFO slots like BoundedSlot and VecDeque,
+    //   and the reverse for LIFO ones.
+    #[cfg(any(feature = "alloc", test))]
+    pub fn sorted_entries(&self) -> alloc::vec::Vec<(usize, &T)> {
+        let mut entries = alloc::vec::Vec::new();
+        self.for_each_entry(|(item, tick)| entries.push((*tick, item)));
+
+        entries.sort_by_key(|(tick, _)| *tick);
+        entries
     }
-}
 
-impl<T, const N: usize> SlotLike for BoundedSlot<T, {N}> {
-    type Item = T;
-
-    fn push(&mut self, i: Self::Item) -> Result<(), T> {
-        if self.size == N {
-            return Err(i);
+    // A panic in f unwinds through the AdvanceTo, whose drop keeps the items not yet fired scheduled,
+    //   so they fire on the next advance instead of being lost, see advance_to.
+    pub fn fast_forward<F: FnMut(T, usize)>(&mut self, moment: usize, mut f: F) {
+        for (item, ts) in self.advance_to(moment) {
+            f(item, ts);
         }
+    }
 
-        unsafe {
-            self.storage[self.size].as_mut_ptr().write(i);
-        }
-        self.size += 1;
-        Ok(())
+    // Put expired items back, to fire first thing on the next advance, in the order given
+    #[cfg(any(feature = "alloc", test))]
+    pub(crate) fn park<I: Iterator<Item = (T, usize)>>(&mut self, items: I) {
+        let mut parked: alloc::vec::Vec<_> = items.collect();
+        parked.reverse();
+        // Anything parked before is older, so it stays at the end
+        parked.append(&mut self.parked);
+        self.parked = parked;
     }
 
-    fn pop(&mut self) -> Option<Self::Item> { 
-        if self.size == 0 {
-            None
-        } else {
-            self.size -= 1;
-            let result = unsafe { self.storage[self.size].as_ptr().read() };
-            Some(result)
+    // Like fast_forward, but the whole batch is fired in (tick, priority) order.
+    // Same-tick ordering otherwise depends on the slot type and on cascading, priority makes it total.
+    // Items with equal keys keep the order fast_forward would have fired them in.
+    #[cfg(any(feature = "alloc", test))]
+    pub fn fast_forward_sorted<K: Ord, P: FnMut(&T) -> K, F: FnMut(T, usize)>(&mut self, moment: usize, mut priority: P, mut f: F) {
+        let mut fired: alloc::vec::Vec<(T, usize)> = self.advance_to(moment).collect();
+        fired.sort_by_cached_key(|(item, ts)| (*ts, priority(item)));
+
+        let mut unfired = Unfired {
+            wheel: self,
+            rest: fired.into_iter(),
+        };
+        while let Some((item, ts)) = unfired.rest.next() {
+            f(item, ts);
         }
     }
 
-    fn size(&self) -> usize { 
-        self.size
+    // Collects everything fired up to moment, then hands the items to f on rayon's thread pool.
+    // Items fired at the same moment are independent, but there's no ordering guarantee whatsoever,
+    //   not even between items of the same tick. Use fast_forward if the callback cares about order.
+    #[cfg(all(feature = "std", feature = "rayon"))]
+    pub fn fast_forward_par<F: Fn(T, usize) + Send + Sync>(&mut self, moment: usize, f: F) where T: Send {
+        use rayon::prelude::*;
+
+        let fired: std::vec::Vec<(T, usize)> = self.advance_to(moment).collect();
+        fired.into_par_iter().for_each(|(item, ts)| f(item, ts));
     }
-}
 
-#[cfg(any(feature="std", test))]
-impl<T> SlotLike for std::collections::VecDeque<T> {
-    type Item = T;
+    // fast_forward for loops calling it on every tick. When nothing fires by moment, only elapsed is moved,
+    //   skipping the cascade machinery. Returns whether the full advance ran.
+    // min_next_event is the start of the earliest occupied slot on any level, so past it no slot needs cascading.
+    //   The overflow is the exception: crossing into a new top-level rotation has to pull it in, due or not.
+    pub fn advance_if_due<F: FnMut(T, usize)>(&mut self, moment: usize, f: F) -> bool {
+        assert!(moment >= self.elapsed());
 
-    fn push(&mut self, i: Self::Item) -> Result<(), Self::Item> {
-        self.push_front(i);
-        Ok(())
+        let idle = match self.min_next_event() {
+            None => true,
+            Some(next) => next > moment,
+        };
+        let pulls_overflow = self.overflow.as_ref().map_or(false, |overflow| overflow.size() != 0)
+            && moment > self.elapsed && self.cascade_level(moment) >= LEVEL;
+
+        if idle && !pulls_overflow {
+            self.elapsed = moment;
+            return false;
+        }
+
+        self.fast_forward(moment, f);
+        true
     }
-    fn pop(&mut self) -> Option<Self::Item> {
-        self.pop_back()
+
+    // Take out everything due at or before elapsed, without moving time forward.
+    // That's whatever a dropped advance parked, then what was scheduled at the current tick.
+    // Items dropped together with the iterator before it's exhausted stay in the wheel.
+    #[cfg(feature = "alloc")]
+    pub fn drain_due(&mut self) -> impl Iterator<Item = (T, usize)> + '_ {
+        let idx = (self.elapsed & ((1 << CUTOFF) - 1)) as u32;
+        let parked = &mut self.parked;
+        core::iter::from_fn(move || parked.pop()).chain(self.levels[0].drain_until(idx + 1))
     }
 
-    fn size(&self) -> usize {
-        self.len()
+    // Without alloc, whatever an interrupted advance left where it was is due too. Picking that advance up yields it,
+    //   along with everything scheduled up to now. Otherwise it's only the slot of the current tick,
+    //   drained the way an advance would, so dropping it early keeps the rest there.
+    #[cfg(not(feature = "alloc"))]
+    pub fn drain_due(&mut self) -> impl Iterator<Item = (T, usize)> + '_ {
+        let now = self.elapsed();
+        if now != self.elapsed {
+            return self.advance(now);
+        }
+
+        let idx = (self.elapsed & ((1 << CUTOFF) - 1)) as u32;
+        AdvanceTo {
+            wheel: self,
+            first_same_wheel: 0,
+            until: idx + 1,
+            level: 0,
+            cascading: None,
+            deferred: None,
+            #[cfg(test)]
+            fire_parked: true,
+            from: now,
+        }
     }
-}
 
-pub type BoundedWheel<T, const N: usize> = Wheel<T, BoundedSlot<(T, usize), N>, 8, 6>;
+    // Advance exactly one tick, for driving the wheel from a periodic timer
+    pub fn tick<F: FnMut(T, usize)>(&mut self, f: F) {
+        let next = self.elapsed() + 1;
+        self.fast_forward(next, f);
+    }
 
-#[cfg(any(feature="std", test))]
-pub type VecDequeWheel<T> = Wheel<T, std::collections::VecDeque<(T, usize)>, 8, 6>;
+    // Jump straight to min_next_event, skipping idle ticks. Returns the moment reached, None if nothing is scheduled.
+    // For an upper level that's the start of a bucket, so the call may only cascade without firing anything.
+    // Items sitting at the current tick are fired without advancing.
+    pub fn tick_to_next<F: FnMut(T, usize)>(&mut self, mut f: F) -> Option<usize> {
+        let next = self.min_next_event()?;
 
-// New
-impl<T, const N: usize, const D: usize> Level<BoundedSlot<T, D>, N> {
-    pub const fn new_bounded() -> Self {
-        Self {
-            bitset: 0,
-            slots: [BoundedSlot::<T, D>::new(); {N}],
+        if next == self.elapsed() {
+            for (item, ts) in self.drain_due() {
+                f(item, ts);
+            }
+        } else {
+            self.fast_forward(next, f);
         }
+
+        Some(next)
     }
-}
 
-impl<T, const LEVEL: usize, const CUTOFF: usize, const D: usize> Wheel<T, BoundedSlot<(T, usize), D>, LEVEL, CUTOFF> {
-    pub const fn new_bounded(at: usize) -> Self {
-        Self {
-            elapsed: at,
-            levels: [WheelLevel::new_bounded(); LEVEL],
+    // Advance to moment, lazily yielding expired items as the levels are cascaded.
+    // elapsed is already moment once this returns. If the iterator is dropped early, the expired items
+    //   not yet yielded stay scheduled, so they fire first on the next advance, and count as due until then.
+    // With alloc they are parked aside. Without, once only the cascading slot is left, the rest of it goes into
+    //   the slot of the current tick, which holds as much. Before that there may be more than a slot holds,
+    //   so they are left where they are, and the levels stay placed for the tick the advance started from.
+    //   The next advance picks up from there, so it fires them first all the same.
+    pub fn advance_to(&mut self, moment: usize) -> AdvanceTo<'_, T, S, LEVEL, CUTOFF> {
+        assert!(moment >= self.elapsed());
+
+        // Advancing to now is a no-op. Items scheduled at elapsed only fire once time actually moves.
+        if self.elapsed() == moment {
+            return AdvanceTo {
+                wheel: self,
+                first_same_wheel: 0,
+                until: 0,
+                level: 1,
+                cascading: None,
+                deferred: None,
+                #[cfg(any(feature = "alloc", test))]
+                fire_parked: false,
+                #[cfg(not(feature = "alloc"))]
+                from: moment,
+            };
         }
-    }
-}
 
-#[cfg(test)]
-mod test {
-    thread_local! {
-        static wheel_basic: core::cell::RefCell<super::BoundedWheel<usize, 16>> = core::cell::RefCell::new(super::BoundedWheel::new_bounded(0));
+        self.advance(moment)
+    }
+
+    // Cascade from wherever the levels are placed for, which is before elapsed() after an interrupted advance without alloc
+    fn advance(&mut self, moment: usize) -> AdvanceTo<'_, T, S, LEVEL, CUTOFF> {
+        #[cfg(not(feature = "alloc"))]
+        {
+            self.interrupted = None;
+        }
+
+        let first_same_wheel = self.cascade_level(moment);
+
+        // Only trace actual cascades, same as crosses_level would tell
+        #[cfg(test)]
+        {
+            if first_same_wheel > 0 {
+                println!("CASCADE: {} => {}, level {}", self.elapsed, moment, first_same_wheel);
+            }
+        }
+
+        // Crossed into a new top-level rotation. Everything in the levels is due,
+        //   and the overflow slot is cascaded in place of a top-level slot.
+        if first_same_wheel >= LEVEL {
+            #[cfg(not(feature = "alloc"))]
+            let from = self.elapsed;
+            self.elapsed = moment;
+            self.overflow_min = usize::MAX;
+            let cascading = self.overflow.take();
+
+            return AdvanceTo {
+                wheel: self,
+                first_same_wheel: LEVEL,
+                until: 0,
+                level: 0,
+                cascading,
+                deferred: None,
+                #[cfg(any(feature = "alloc", test))]
+                fire_parked: true,
+                #[cfg(not(feature = "alloc"))]
+                from,
+            };
+        }
+
+        // Skipped slots to clear on first_same_wheel
+        let idx_mask = (1 << CUTOFF) - 1;
+        let from_idx = (self.elapsed >> (CUTOFF * first_same_wheel)) & idx_mask;
+        let to_idx = (moment >> (CUTOFF * first_same_wheel)) & idx_mask;
+
+        #[cfg(test)]
+        println!("| {} -> {}", from_idx, to_idx);
+
+        // to_idx != from_idx implies to_idx > 0
+        let until = if to_idx != from_idx { to_idx as u32 } else { 0 };
+
+        #[cfg(not(feature = "alloc"))]
+        let from = self.elapsed;
+        self.elapsed = moment;
+
+        // The cascading slot is not touched by draining the skipped ones, so take it out right away
+        let spare = self.spare.take().unwrap_or_default();
+        let cascading = self.levels[first_same_wheel].replace_slot(to_idx as u32, spare);
+
+        AdvanceTo {
+            wheel: self,
+            first_same_wheel,
+            until,
+            level: 0,
+            cascading: Some(cascading),
+            deferred: None,
+            #[cfg(any(feature = "alloc", test))]
+            fire_parked: true,
+            #[cfg(not(feature = "alloc"))]
+            from,
+        }
+    }
+
+    // The highest level whose index changes on the way from elapsed to moment, which is where the cascade starts.
+    // LEVEL or above means moment is in another top-level rotation. Only meaningful for moment > elapsed.
+    fn cascade_level(&self, moment: usize) -> usize {
+        let same_leading = (self.elapsed ^ moment).leading_zeros();
+        const BITCOUNT_TOT: usize = core::mem::size_of::<usize>() * 8;
+        let first_one = BITCOUNT_TOT - same_leading as usize - 1;
+        first_one / CUTOFF
+    }
+
+    // Whether advancing to moment cascades items down from a level above 0, e.g. to decide on tracing it.
+    // Cheap, nothing is touched.
+    pub fn crosses_level(&self, moment: usize) -> bool {
+        moment > self.elapsed && self.cascade_level(moment) > 0
+    }
+
+    // The tick at which `level` next rolls its index, cascading whatever sits in the slot it moves onto.
+    // Level 0 rolls on every tick.
+    pub fn next_cascade_at(&self, level: usize) -> usize {
+        assert!(level < LEVEL);
+
+        let shift = level * CUTOFF;
+        ((self.elapsed >> shift) + 1) << shift
+    }
+
+    // Get the minimal possible time for the next event
+    pub fn min_next_event(&self) -> Option<usize> {
+        self.min_next_event_detailed().map(|(tick, _)| tick)
+    }
+
+    // min_next_event, along with the number of items in the slot it was found in.
+    // On level 0 that's exactly the items due at that tick. A higher slot covers a range of ticks,
+    //   and so does the overflow, so there it's only an upper bound.
+    pub fn min_next_event_detailed(&self) -> Option<(usize, usize)> {
+        // So are the leftovers of an interrupted advance without alloc, the levels are still placed for before now
+        #[cfg(not(feature = "alloc"))]
+        {
+            let now = self.elapsed();
+            if now != self.elapsed {
+                return Some((now, self.count_between(0, now.saturating_add(1))));
+            }
+        }
+
+        // Parked items are overdue, they share the current tick with whatever is scheduled there
+        #[cfg(any(feature = "alloc", test))]
+        {
+            if !self.parked.is_empty() {
+                let idx = (self.elapsed & ((1 << CUTOFF) - 1)) as u32;
+                return Some((self.elapsed, self.parked.len() + self.levels[0].slot_size(idx)));
+            }
+        }
+
+        let mut left = self.elapsed;
+
+        for i in 0..LEVEL {
+            let tail = (left & ((1<<CUTOFF) - 1)) as u32;
+            left >>= CUTOFF;
+
+            if let Some(ev) = self.levels[i].next_event(tail) {
+                let ret_high = (left << CUTOFF) | ev as usize;
+                return Some((ret_high << (i * CUTOFF), self.levels[i].slot_size(ev)));
+            }
+        }
+
+        // Overflowed items are all beyond the horizon, so they only matter once the levels are empty
+        match &self.overflow {
+            Some(overflow) if overflow.size() != 0 => Some((self.overflow_min, overflow.size())),
+            _ => None,
+        }
+    }
+
+    // Drops everything scheduled, overflowed items included. elapsed stays where it is.
+    pub fn clear(&mut self) {
+        for level in self.levels.iter_mut() {
+            for _ in level.drain() {}
+        }
+
+        // Popped one by one, not every slot type drops its items along with itself
+        if let Some(overflow) = &mut self.overflow {
+            while overflow.pop().is_some() {}
+        }
+        self.overflow_min = usize::MAX;
+
+        #[cfg(any(feature = "alloc", test))]
+        self.parked.clear();
+
+        // Nothing left to pick up, so the levels can be placed for now again
+        #[cfg(not(feature = "alloc"))]
+        {
+            self.elapsed = self.elapsed();
+            self.interrupted = None;
+        }
+    }
+
+    // clear, then continue from `at`, which may also lie in the past
+    pub fn reset_to(&mut self, at: usize) {
+        self.clear();
+        self.elapsed = at;
+    }
+
+    // Scheduled items, including the overflowed ones. Walks every occupied slot.
+    pub fn pending_count(&self) -> usize {
+        let overflowed = self.overflow.as_ref().map_or(0, |overflow| overflow.size());
+        #[cfg(any(feature = "alloc", test))]
+        let overflowed = overflowed + self.parked.len();
+        self.levels.iter().map(|level| level.len()).sum::<usize>() + overflowed
+    }
+
+    // Items per level, bottom first. Overflowed and parked items aren't in any level.
+    pub fn level_histogram(&self) -> [usize; LEVEL] {
+        let mut histogram = [0; LEVEL];
+        for (count, level) in histogram.iter_mut().zip(self.levels.iter()) {
+            *count = level.len();
+        }
+        histogram
+    }
+
+    // How full each level is, bottom first, for spotting timers clustering on a few slots.
+    // With bounded slots it's items over what the level can hold. Unbounded slots have no such limit,
+    //   so it's the fraction of slots occupied instead.
+    pub fn level_fill_ratio(&self) -> [f32; LEVEL] {
+        let mut ratios = [0.0; LEVEL];
+        for (ratio, level) in ratios.iter_mut().zip(self.levels.iter()) {
+            *ratio = level.fill_ratio();
+        }
+        ratios
+    }
+
+    // Up to k distinct upcoming deadlines, ascending, without firing anything.
+    // Like min_next_event, a slot above level 0 holds a range of ticks that hasn't been cascaded yet,
+    //   so it's reported once, as the earliest tick it could fire at. Use occupied_ticks for exact ticks.
+    pub fn peek_next(&self, k: usize) -> impl Iterator<Item = usize> + '_ {
+        let overflow_min = match &self.overflow {
+            Some(overflow) if overflow.size() != 0 => Some(self.overflow_min),
+            _ => None,
+        };
+
+        // Parked items are due now, which the current slot reports by itself if it's occupied
+        #[cfg(any(feature = "alloc", test))]
+        let parked = !self.parked.is_empty() && self.levels[0].slot_size((self.elapsed & ((1 << CUTOFF) - 1)) as u32) == 0;
+        #[cfg(not(any(feature = "alloc", test)))]
+        let parked = false;
+        // Leftovers of an interrupted advance are due now too, their slots are placed for before now
+        #[cfg(not(feature = "alloc"))]
+        let parked = parked || self.elapsed() != self.elapsed;
+        let parked_at = if parked { Some(self.elapsed()) } else { None };
+
+        parked_at.into_iter().chain(self.levels.iter().enumerate().flat_map(move |(i, level)| {
+            // Same arithmetic as min_next_event, the bits above this level come from elapsed
+            let left = (0..=i).fold(self.elapsed, |left, _| left >> CUTOFF);
+            level.occupied().map(move |idx| ((left << CUTOFF) | idx as usize) << (i * CUTOFF))
+        }).filter(move |tick| parked_at.map_or(true, |at| *tick > at))).chain(overflow_min).take(k)
+    }
+}
+
+// Doesn't need T: Debug, only the timing is shown
+impl<T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> core::fmt::Debug for Wheel<T, S, LEVEL, CUTOFF> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Wheel")
+            .field("elapsed", &self.elapsed())
+            .field("min_next_event", &self.min_next_event())
+            .field("pending_count", &self.pending_count())
+            .finish()
+    }
+}
+
+// Saved as elapsed and the flat list of pending (tick, item), slot by slot. The level structure is rebuilt on load,
+//   see from_events. Every item lands back in the slot it came from, behind the ones it was behind before,
+//   so even a long jump fires them in the same order. The occupancy cap is configuration rather than state, and isn't saved.
+// Items an interrupted advance parked are overdue, so from_events would refuse them. They are saved on their own, oldest first,
+//   and so are the ones it left in their slots without alloc.
+#[cfg(all(feature = "serde", any(feature = "alloc", test)))]
+#[derive(serde::Serialize)]
+struct WheelStateRef<'a, T> {
+    elapsed: usize,
+    events: alloc::vec::Vec<(usize, &'a T)>,
+    parked: alloc::vec::Vec<(usize, &'a T)>,
+}
+
+#[cfg(all(feature = "serde", any(feature = "alloc", test)))]
+#[derive(serde::Deserialize)]
+struct WheelState<T> {
+    elapsed: usize,
+    events: alloc::vec::Vec<(usize, T)>,
+    #[serde(default)]
+    parked: alloc::vec::Vec<(usize, T)>,
+}
+
+#[cfg(all(feature = "serde", any(feature = "alloc", test)))]
+impl<T: serde::Serialize, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> serde::Serialize for Wheel<T, S, LEVEL, CUTOFF> {
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        let elapsed = self.elapsed();
+        let mut events = alloc::vec::Vec::with_capacity(self.pending_count());
+        let mut overdue = alloc::vec::Vec::new();
+        self.for_each_slotted(|(item, tick)| {
+            if *tick < elapsed {
+                overdue.push((*tick, item));
+            } else {
+                events.push((*tick, item));
+            }
+        });
+
+        // They fire after the parked ones, in tick order
+        overdue.sort_by_key(|(tick, _)| *tick);
+
+        WheelStateRef {
+            elapsed,
+            events,
+            parked: self.parked.iter().rev().map(|(item, tick)| (*tick, item)).chain(overdue).collect(),
+        }.serialize(serializer)
+    }
+}
+
+#[cfg(all(feature = "serde", any(feature = "alloc", test)))]
+impl<'de, T: serde::Deserialize<'de>, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> serde::Deserialize<'de> for Wheel<T, S, LEVEL, CUTOFF> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let state = WheelState::<T>::deserialize(deserializer)?;
+        let elapsed = state.elapsed;
+        let mut wheel = Self::from_events(elapsed, state.events).map_err(|(tick, _)| {
+            D::Error::custom(format_args!("event at tick {} can't be scheduled at elapsed {}, it's in the past or its slot is full", tick, elapsed))
+        })?;
+
+        // Parked items are overdue, one still in the future would be misplaced
+        if let Some((tick, _)) = state.parked.iter().find(|(tick, _)| *tick > elapsed) {
+            return Err(D::Error::custom(format_args!("parked event at tick {} isn't due yet at elapsed {}", tick, elapsed)));
+        }
+        wheel.park(state.parked.into_iter().map(|(tick, item)| (item, tick)));
+
+        Ok(wheel)
+    }
+}
+
+// The expired items of advance_to, cascaded lazily.
+// The wheel is mid-cascade until this is exhausted or dropped, so it stays borrowed mutably all along:
+/// ```compile_fail,E0499
+/// use queueue::timing_wheel::hierarchical::BoundedWheel;
+///
+/// let mut wheel = BoundedWheel::<usize, 4>::new_bounded(0);
+/// wheel.schedule(5, 0).unwrap();
+///
+/// let mut expired = wheel.advance_to(10);
+/// wheel.schedule(20, 1).unwrap();
+/// expired.next();
+/// ```
+pub struct AdvanceTo<'a, T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> {
+    wheel: &'a mut Wheel<T, S, LEVEL, CUTOFF>,
+    // Levels below first_same_wheel are drained entirely, first_same_wheel is drained until `until`
+    first_same_wheel: usize,
+    until: u32,
+    // The level currently being drained
+    level: usize,
+    cascading: Option<S>,
+    // Items to put back from the cascading slot, for LIFO slots, see next()
+    deferred: Option<S>,
+    // Whether what an interrupted advance parked fires first. Not on a no-op advance, same as the items scheduled now.
+    #[cfg(any(feature = "alloc", test))]
+    fire_parked: bool,
+    // What the levels were placed for before the advance, to go back to if the leftovers don't fit a slot, see park_in_place
+    #[cfg(not(feature = "alloc"))]
+    from: usize,
+}
+
+impl<'a, T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> Iterator for AdvanceTo<'a, T, S, LEVEL, CUTOFF> {
+    type Item = (T, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        #[cfg(any(feature = "alloc", test))]
+        {
+            if self.fire_parked {
+                if let Some(item) = self.wheel.parked.pop() {
+                    return Some(item);
+                }
+            }
+        }
+
+        // Clear all bottom queues
+        while self.level < self.first_same_wheel {
+            // Draining in place is faster than replacing
+            if let Some(item) = self.wheel.levels[self.level].drain().next() {
+                return Some(item);
+            }
+            self.level += 1;
+        }
+
+        // Clear skipped slots
+        if self.level == self.first_same_wheel && self.level < LEVEL {
+            if let Some(item) = self.wheel.levels[self.level].drain_until(self.until).next() {
+                return Some(item);
+            }
+            self.level += 1;
+        }
+
+        // Items still in the future go back into the (now empty) lower levels
+        let moment = self.wheel.elapsed;
+        let cascading = self.cascading.as_mut()?;
+        while let Some((item, ts)) = cascading.pop() {
+            if ts <= moment {
+                return Some((item, ts));
+            } else if S::ORDER == SlotOrder::Lifo {
+                // Popping a stack and pushing straight back onto the lower ones would turn it upside down.
+                // Stacked once more here, they come out oldest first and go back in the order they were scheduled.
+                let _ = self.deferred.get_or_insert_with(S::default).push((item, ts));
+            } else {
+                self.wheel.cascade_back(ts, item);
+            }
+        }
+
+        if let Some(mut deferred) = self.deferred.take() {
+            while let Some((item, ts)) = deferred.pop() {
+                self.wheel.cascade_back(ts, item);
+            }
+        }
+
+        // Empty now, recycle it for the next cascade
+        self.wheel.spare = self.cascading.take();
+        None
+    }
+}
+
+#[cfg(not(feature = "alloc"))]
+impl<'a, T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> AdvanceTo<'a, T, S, LEVEL, CUTOFF> {
+    // Whether expired items are still waiting in the levels below the cascading slot, placed for the old elapsed
+    fn due_below(&self) -> bool {
+        let below = core::cmp::min(self.first_same_wheel, LEVEL);
+        if (self.level..below).any(|level| self.wheel.levels[level].bitset != 0) {
+            return true;
+        }
+
+        // trailing_zeros of an empty bitset is 64, past any until
+        self.level <= self.first_same_wheel && self.first_same_wheel < LEVEL
+            && self.wheel.levels[self.first_same_wheel].bitset.trailing_zeros() < self.until
+    }
+
+    // Without alloc, the slot of the current tick is the only place to park. It's empty right after the cascade,
+    //   and holds as many as the cascading slot, so it takes whatever is left once only that one is.
+    // Before that, the leftovers are spread over the lower levels and may be more than a slot holds,
+    //   so they stay where they are, see leave_in_place.
+    fn park_in_place(&mut self) {
+        if self.due_below() {
+            self.leave_in_place();
+            return;
+        }
+
+        let mut rest = S::default();
+        for item in self.by_ref() {
+            if rest.push(item).is_err() {
+                unreachable!("Only the cascading slot was left, and a slot of the same type holds as much");
+            }
+        }
+
+        let idx = (self.wheel.elapsed & ((1 << CUTOFF) - 1)) as u32;
+        while let Some(item) = rest.pop() {
+            if self.wheel.levels[0].push_at(idx, item).is_err() {
+                unreachable!("The slot of the current tick is empty right after a cascade");
+            }
+        }
+    }
+
+    // Only before the cascading slot is touched, so nothing has been put back into the levels for the new elapsed yet.
+    // With the cascading slot back where it was taken from, the levels are placed for where the advance started,
+    //   leftovers included. elapsed() still says moment, the next advance picks up from there.
+    fn leave_in_place(&mut self) {
+        let moment = self.wheel.elapsed;
+        self.wheel.elapsed = self.from;
+        if moment != self.from {
+            self.wheel.interrupted = Some(moment);
+        }
+
+        if self.first_same_wheel >= LEVEL {
+            self.wheel.overflow = self.cascading.take();
+            self.wheel.update_overflow_min();
+        } else if let Some(cascading) = self.cascading.take() {
+            let idx = (moment >> (CUTOFF * self.first_same_wheel)) & ((1 << CUTOFF) - 1);
+            let spare = self.wheel.levels[self.first_same_wheel].replace_slot(idx as u32, cascading);
+            self.wheel.spare = Some(spare);
+        }
+    }
+}
+
+impl<'a, T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> Drop for AdvanceTo<'a, T, S, LEVEL, CUTOFF> {
+    fn drop(&mut self) {
+        #[cfg(feature = "alloc")]
+        {
+            let rest: alloc::vec::Vec<_> = self.by_ref().collect();
+            self.wheel.park(rest.into_iter());
+        }
+
+        #[cfg(not(feature = "alloc"))]
+        self.park_in_place();
+    }
+}
+
+// Items of a batch yet to fire. Parks them back into the wheel if dropped early, which only happens if the callback panics.
+#[cfg(any(feature = "alloc", test))]
+struct Unfired<'a, T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> {
+    wheel: &'a mut Wheel<T, S, LEVEL, CUTOFF>,
+    rest: alloc::vec::IntoIter<(T, usize)>,
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<'a, T, S: SlotLike<Item = (T, usize)>, const LEVEL: usize, const CUTOFF: usize> Drop for Unfired<'a, T, S, LEVEL, CUTOFF> {
+    fn drop(&mut self) {
+        self.wheel.park(&mut self.rest);
+    }
+}
+
+// A fixed-capacity ring buffer. Items come out in the order they went in, SlotOrder::Fifo like the VecDeque slot,
+//   so same-tick events fire in the same order whichever of the two backs the wheel.
+pub struct BoundedSlot<T, const N: usize> {
+    storage: [MaybeUninit<T>; {N}],
+    head: usize,
+    size: usize,
+}
+
+impl<T, const N: usize> Default for BoundedSlot<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> BoundedSlot<T, {N}> {
+    pub const fn new() -> Self {
+        Self {
+            storage: [MaybeUninit::UNINIT; {N}],
+            head: 0,
+            size: 0,
+        }
+    }
+}
+
+// The storage is MaybeUninit, which never drops anything on its own
+impl<T, const N: usize> Drop for BoundedSlot<T, {N}> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+impl<T, const N: usize> SlotLike for BoundedSlot<T, {N}> {
+    type Item = T;
+    const ORDER: SlotOrder = SlotOrder::Fifo;
+
+    fn push(&mut self, i: Self::Item) -> Result<(), T> {
+        if self.size == N {
+            return Err(i);
+        }
+
+        let idx = (self.head + self.size) % N;
+        unsafe {
+            self.storage[idx].as_mut_ptr().write(i);
+        }
+        self.size += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<Self::Item> { 
+        if self.size == 0 {
+            None
+        } else {
+            let result = unsafe { self.storage[self.head].as_ptr().read() };
+            self.head = (self.head + 1) % N;
+            self.size -= 1;
+            Some(result)
+        }
+    }
+
+    fn size(&self) -> usize { 
+        self.size
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        Some(N)
+    }
+
+    fn for_each_item<'a, F: FnMut(&'a Self::Item)>(&'a self, mut f: F) {
+        for i in 0..self.size {
+            let item = &self.storage[(self.head + i) % N];
+            f(unsafe { &*item.as_ptr() });
+        }
+    }
+}
+
+#[cfg(any(feature="std", test))]
+impl<T> SlotLike for std::collections::VecDeque<T> {
+    type Item = T;
+    const ORDER: SlotOrder = SlotOrder::Fifo;
+
+    fn push(&mut self, i: Self::Item) -> Result<(), Self::Item> {
+        self.push_front(i);
+        Ok(())
+    }
+    fn pop(&mut self) -> Option<Self::Item> {
+        self.pop_back()
+    }
+
+    fn size(&self) -> usize {
+        self.len()
+    }
+
+    // Back to front, the order pop takes them
+    fn for_each_item<'a, F: FnMut(&'a Self::Item)>(&'a self, f: F) {
+        self.iter().rev().for_each(f);
+    }
+}
+
+// The VecDeque slot, refusing items once it holds MAX of them instead of growing without bound.
+// Heap-backed like VecDeque, with the backpressure of BoundedSlot. Memory is only taken as items come in.
+#[cfg(any(feature = "alloc", test))]
+pub struct CappedVecDequeSlot<T, const MAX: usize>(alloc::collections::VecDeque<T>);
+
+#[cfg(any(feature = "alloc", test))]
+impl<T, const MAX: usize> Default for CappedVecDequeSlot<T, {MAX}> {
+    fn default() -> Self {
+        CappedVecDequeSlot(alloc::collections::VecDeque::new())
+    }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<T, const MAX: usize> SlotLike for CappedVecDequeSlot<T, {MAX}> {
+    type Item = T;
+    const ORDER: SlotOrder = SlotOrder::Fifo;
+
+    fn push(&mut self, i: Self::Item) -> Result<(), Self::Item> {
+        if self.0.len() >= MAX {
+            return Err(i);
+        }
+
+        self.0.push_front(i);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<Self::Item> {
+        self.0.pop_back()
+    }
+
+    fn size(&self) -> usize {
+        self.0.len()
+    }
+
+    fn for_each_item<'a, F: FnMut(&'a Self::Item)>(&'a self, f: F) {
+        self.0.iter().rev().for_each(f);
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        Some(MAX)
+    }
+}
+
+// Hands out the most recently scheduled item first, for schedulers that want what's still hot in cache
+#[cfg(any(feature = "alloc", test))]
+pub struct LifoSlot<T>(alloc::vec::Vec<T>);
+
+#[cfg(any(feature = "alloc", test))]
+impl<T> Default for LifoSlot<T> {
+    fn default() -> Self {
+        LifoSlot(alloc::vec::Vec::new())
+    }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<T> SlotLike for LifoSlot<T> {
+    type Item = T;
+    const ORDER: SlotOrder = SlotOrder::Lifo;
+
+    fn push(&mut self, i: Self::Item) -> Result<(), Self::Item> {
+        self.0.push(i);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<Self::Item> {
+        self.0.pop()
+    }
+
+    fn size(&self) -> usize {
+        self.0.len()
+    }
+
+    // Bottom to top, the order they were pushed in
+    fn for_each_item<'a, F: FnMut(&'a Self::Item)>(&'a self, f: F) {
+        self.0.iter().for_each(f);
+    }
+}
+
+// An item with a priority, lower fires first among items of the same tick
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prioritized<T> {
+    pub priority: u32,
+    pub item: T,
+}
+
+// Only ordered by (tick, priority), the payload doesn't need to be Ord
+#[cfg(any(feature = "alloc", test))]
+struct PriorityEntry<T>((Prioritized<T>, usize));
+
+#[cfg(any(feature = "alloc", test))]
+impl<T> PriorityEntry<T> {
+    fn key(&self) -> (usize, u32) {
+        ((self.0).1, (self.0).0.priority)
+    }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<T> PartialEq for PriorityEntry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<T> Eq for PriorityEntry<T> {}
+
+#[cfg(any(feature = "alloc", test))]
+impl<T> PartialOrd for PriorityEntry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Reversed, BinaryHeap pops the greatest
+#[cfg(any(feature = "alloc", test))]
+impl<T> Ord for PriorityEntry<T> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        other.key().cmp(&self.key())
+    }
+}
+
+// Pops the earliest tick first, and the lowest priority among items of that tick. Ties are arbitrary.
+// A level 0 slot only holds a single tick, so that's priority order within a tick,
+//   and items cascading out of a higher slot come out in tick order as a bonus.
+#[cfg(any(feature = "alloc", test))]
+pub struct PrioritySlot<T> {
+    heap: alloc::collections::BinaryHeap<PriorityEntry<T>>,
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<T> Default for PrioritySlot<T> {
+    fn default() -> Self {
+        Self {
+            heap: alloc::collections::BinaryHeap::new(),
+        }
+    }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<T> SlotLike for PrioritySlot<T> {
+    type Item = (Prioritized<T>, usize);
+
+    fn push(&mut self, i: Self::Item) -> Result<(), Self::Item> {
+        self.heap.push(PriorityEntry(i));
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<Self::Item> {
+        self.heap.pop().map(|entry| entry.0)
+    }
+
+    fn size(&self) -> usize {
+        self.heap.len()
+    }
+
+    fn for_each_item<'a, F: FnMut(&'a Self::Item)>(&'a self, mut f: F) {
+        for entry in self.heap.iter() {
+            f(&entry.0);
+        }
+    }
+}
+
+impl<T, S: SlotLike<Item = (Prioritized<T>, usize)>, const LEVEL: usize, const CUTOFF: usize> Wheel<Prioritized<T>, S, LEVEL, CUTOFF> {
+    // Only orders items within a tick. Whether the order holds depends on the slot type, see PrioritySlot.
+    pub fn schedule_with_priority(&mut self, tick: usize, priority: u32, item: T) -> Result<(), T> {
+        self.schedule(tick, Prioritized { priority, item }).map_err(|rejected| rejected.item)
+    }
+}
+
+// The geometry all the aliases below use: 64 slots a level, and enough levels for a whole 48-bit tick
+pub type SlotWheel<T, S> = Wheel<T, S, 8, 6>;
+
+pub type BoundedWheel<T, const N: usize> = Wheel<T, BoundedSlot<(T, usize), N>, 8, 6>;
+
+#[cfg(any(feature="std", test))]
+pub type VecDequeWheel<T> = Wheel<T, std::collections::VecDeque<(T, usize)>, 8, 6>;
+
+#[cfg(any(feature = "alloc", test))]
+pub type CappedVecDequeWheel<T, const MAX: usize> = Wheel<T, CappedVecDequeSlot<(T, usize), MAX>, 8, 6>;
+
+#[cfg(any(feature = "alloc", test))]
+pub type LifoWheel<T> = Wheel<T, LifoSlot<(T, usize)>, 8, 6>;
+
+#[cfg(any(feature = "alloc", test))]
+pub type PriorityWheel<T> = Wheel<Prioritized<T>, PrioritySlot<T>, 8, 6>;
+
+// New
+impl<T, const N: usize, const D: usize> Level<BoundedSlot<T, D>, N> {
+    pub const fn new_bounded() -> Self {
+        let _ = Self::FITS_BITSET;
+
+        Self {
+            bitset: 0,
+            slots: [BoundedSlot::<T, D>::new(); {N}],
+        }
+    }
+}
+
+impl<T, const LEVEL: usize, const CUTOFF: usize, const D: usize> Wheel<T, BoundedSlot<(T, usize), D>, LEVEL, CUTOFF> {
+    pub const fn new_bounded(at: usize) -> Self {
+        let _ = Self::CUTOFF_FITS;
+
+        Self {
+            elapsed: at,
+            levels: [WheelLevel::new_bounded(); LEVEL],
+            max_slot_occupancy: usize::MAX,
+            spare: None,
+            overflow: None,
+            overflow_min: usize::MAX,
+            #[cfg(any(feature = "alloc", test))]
+            parked: alloc::vec::Vec::new(),
+            #[cfg(not(feature = "alloc"))]
+            interrupted: None,
+        }
+    }
+}
+
+// Boxed closures as items turn the wheel into a minimal deferred task scheduler
+#[cfg(any(feature = "alloc", test))]
+pub type Task = alloc::boxed::Box<dyn FnOnce() + Send>;
+
+#[cfg(any(feature = "alloc", test))]
+impl<S: SlotLike<Item = (Task, usize)>, const LEVEL: usize, const CUTOFF: usize> Wheel<Task, S, LEVEL, CUTOFF> {
+    // On Err the closure is handed back already boxed
+    pub fn schedule_fn<F: FnOnce() + Send + 'static>(&mut self, tick: usize, f: F) -> Result<(), Task> {
+        self.schedule(tick, alloc::boxed::Box::new(f))
+    }
+
+    // Runs every task due up to moment, in the order fast_forward fires them
+    pub fn run_due(&mut self, moment: usize) {
+        self.fast_forward(moment, |task, _| task());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    thread_local! {
+        static wheel_basic: core::cell::RefCell<super::BoundedWheel<usize, 16>> = core::cell::RefCell::new(super::BoundedWheel::new_bounded(0));
     }
 
     #[test]
@@ -364,6 +1682,1120 @@ mod test {
         });
     }
 
+    #[test]
+    fn max_slot_occupancy() {
+        let mut wheel = super::VecDequeWheel::new(0).with_max_slot_occupancy(2);
+        wheel.schedule(5, 1).unwrap();
+        wheel.schedule(5, 2).unwrap();
+        assert_eq!(wheel.schedule(5, 3), Err(3));
+        wheel.schedule(6, 3).unwrap();
+
+        // 100 and 120 share a level 1 slot
+        wheel.schedule(100, 4).unwrap();
+        wheel.schedule(120, 5).unwrap();
+        assert_eq!(wheel.schedule(127, 6), Err(6));
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(5, |item, _| fired.push(item));
+        assert_eq!(fired, vec![1, 2]);
+        wheel.schedule(6, 7).unwrap();
+        assert_eq!(wheel.schedule(6, 8), Err(8));
+
+        // Nothing gets lost when cascading
+        wheel.fast_forward(200, |item, _| fired.push(item));
+        assert_eq!(fired, vec![1, 2, 3, 7, 4, 5]);
+    }
+
+    #[test]
+    fn schedule_many() {
+        let mut wheel = super::VecDequeWheel::new(0).with_max_slot_occupancy(2);
+        wheel.fast_forward(10, |_, _| {});
+        wheel.schedule(12, 0).unwrap();
+
+        let horizon = 10 + (1 << 48);
+        let result = wheel.schedule_many(vec![
+            (12, 1), (3, 2), (20, 3), (12, 4), (horizon, 5), (100, 6), (20, 7), (120, 8), (12, 9),
+        ]);
+        // Past, beyond the horizon, and the slot of tick 12 holding 2 already
+        assert_eq!(result, Err(vec![(3, 2), (12, 4), (horizon, 5), (12, 9)]));
+
+        assert_eq!(wheel.sorted_entries(), vec![(12, &0), (12, &1), (20, &3), (20, &7), (100, &6), (120, &8)]);
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(200, |item, at| fired.push((at, item)));
+        assert_eq!(fired, vec![(12, 0), (12, 1), (20, 3), (20, 7), (100, 6), (120, 8)]);
+
+        assert_eq!(wheel.schedule_many(vec![(201, 0), (300, 1)]), Ok(()));
+        assert_eq!(wheel.schedule_many(Vec::new()), Ok(()));
+    }
+
+    #[test]
+    fn tick() {
+        let mut wheel = super::VecDequeWheel::new(0);
+        wheel.schedule(1, 1).unwrap();
+        wheel.schedule(3, 2).unwrap();
+
+        wheel.tick(|item, at| assert_eq!((item, at), (1, 1)));
+        wheel.tick(|_, _| panic!());
+        wheel.tick(|item, at| assert_eq!((item, at), (2, 3)));
+        assert_eq!(wheel.elapsed(), 3);
+    }
+
+    #[test]
+    fn tick_to_next() {
+        let mut wheel = super::VecDequeWheel::new(0);
+        let ticks = [7, 7, 8, 63, 64, 70, 70, 130, 4000, 4097, 262143, 262145];
+        for (idx, tick) in ticks.iter().enumerate() {
+            wheel.schedule(*tick, idx).unwrap();
+        }
+        wheel.schedule(0, ticks.len()).unwrap();
+
+        let mut fired = Vec::new();
+        let mut last = 0;
+        while let Some(moment) = wheel.tick_to_next(|item, at| fired.push((at, item))) {
+            assert!(moment >= last);
+            assert_eq!(wheel.elapsed(), moment);
+            last = moment;
+        }
+
+        let mut expected: Vec<_> = ticks.iter().cloned().zip(0..).collect();
+        expected.insert(0, (0, ticks.len()));
+        assert_eq!(fired, expected);
+        assert_eq!(wheel.tick_to_next(|_, _| panic!()), None);
+    }
+
+    #[test]
+    fn next_cascade_at() {
+        let mut wheel = super::VecDequeWheel::new(0);
+        assert_eq!(wheel.next_cascade_at(0), 1);
+        assert_eq!(wheel.next_cascade_at(1), 64);
+        assert_eq!(wheel.next_cascade_at(2), 4096);
+
+        wheel.fast_forward(10, |_, _| panic!());
+        assert_eq!(wheel.next_cascade_at(0), 11);
+        assert_eq!(wheel.next_cascade_at(1), 64);
+
+        // Sits in level 1 until it rolls over at 64
+        wheel.schedule(100, 1).unwrap();
+        assert_eq!(wheel.min_next_event(), Some(64));
+        wheel.fast_forward(63, |_, _| panic!());
+        assert_eq!(wheel.next_cascade_at(1), 64);
+        assert_eq!(wheel.min_next_event(), Some(64));
+
+        wheel.fast_forward(64, |_, _| panic!());
+        assert_eq!(wheel.min_next_event(), Some(100));
+        assert_eq!(wheel.next_cascade_at(0), 65);
+        assert_eq!(wheel.next_cascade_at(1), 128);
+
+        wheel.fast_forward(200, |item, _| assert_eq!(item, 1));
+        assert_eq!(wheel.next_cascade_at(0), 201);
+        assert_eq!(wheel.next_cascade_at(1), 256);
+        assert_eq!(wheel.next_cascade_at(2), 4096);
+    }
+
+    #[test]
+    fn advance_to_now() {
+        let mut wheel = super::VecDequeWheel::new(0);
+        wheel.fast_forward(0, |_, _| panic!());
+
+        wheel.schedule(10, 1).unwrap();
+        wheel.schedule(100, 2).unwrap();
+        wheel.fast_forward(10, |item, _| assert_eq!(item, 1));
+
+        wheel.fast_forward(10, |_, _| panic!());
+        assert_eq!(wheel.elapsed(), 10);
+        assert_eq!(wheel.min_next_event(), Some(64));
+
+        // Scheduled at the current tick, still waits for the next advance
+        wheel.schedule(10, 3).unwrap();
+        wheel.fast_forward(10, |_, _| panic!());
+        assert_eq!(wheel.min_next_event(), Some(10));
+        wheel.fast_forward(11, |item, _| assert_eq!(item, 3));
+        assert_eq!(wheel.min_next_event(), Some(64));
+    }
+
+    #[test]
+    fn slot_order() {
+        // 70 and 80 are cascaded from level 1 on the way, which mustn't change their order
+        fn fired<S: super::SlotLike<Item = (usize, usize)>>(mut wheel: super::Wheel<usize, S, 8, 6>) -> Vec<usize> {
+            for (i, tick) in [5, 5, 5, 70, 70, 80, 80].iter().enumerate() {
+                wheel.schedule(*tick, i).unwrap();
+            }
+
+            let mut fired = Vec::new();
+            wheel.fast_forward(10, |item, _| fired.push(item));
+            wheel.fast_forward(70, |item, _| fired.push(item));
+            wheel.fast_forward(100, |item, _| fired.push(item));
+            fired
+        }
+
+        let fifo = super::VecDequeWheel::new(0);
+        assert_eq!(fifo.slot_order(), super::SlotOrder::Fifo);
+        assert_eq!(fired(fifo), vec![0, 1, 2, 3, 4, 5, 6]);
+
+        let bounded = super::BoundedWheel::<usize, 4>::new(0);
+        assert_eq!(bounded.slot_order(), super::SlotOrder::Fifo);
+        assert_eq!(fired(bounded), vec![0, 1, 2, 3, 4, 5, 6]);
+
+        let lifo = super::LifoWheel::new(0);
+        assert_eq!(lifo.slot_order(), super::SlotOrder::Lifo);
+        assert_eq!(fired(lifo), vec![2, 1, 0, 4, 3, 6, 5]);
+
+        assert_eq!(super::PriorityWheel::<usize>::new(0).slot_order(), super::SlotOrder::Unspecified);
+    }
+
+    #[test]
+    fn remove() {
+        // The rest of the slot keeps its order, whichever order the slot has
+        fn fired<S: super::SlotLike<Item = (usize, usize)>>(mut wheel: super::Wheel<usize, S, 8, 6>) -> Vec<usize> {
+            for (i, tick) in [70, 70, 70, 70].iter().enumerate() {
+                wheel.schedule(*tick, i).unwrap();
+            }
+            assert_eq!(wheel.remove(70, |i| *i == 2), Some(2));
+            assert_eq!(wheel.remove(70, |i| *i == 2), None);
+            assert_eq!(wheel.remove(71, |_| true), None);
+
+            let mut fired = Vec::new();
+            wheel.fast_forward(100, |item, _| fired.push(item));
+            fired
+        }
+
+        assert_eq!(fired(super::VecDequeWheel::new(0)), vec![0, 1, 3]);
+        assert_eq!(fired(super::LifoWheel::new(0)), vec![3, 1, 0]);
+
+        let mut wheel = super::Wheel::<usize, std::collections::VecDeque<(usize, usize)>, 2, 6>::new(0);
+        wheel.schedule(10, 0).unwrap();
+        wheel.schedule_far(5000, 1).unwrap();
+        wheel.schedule_far(6000, 2).unwrap();
+        assert_eq!(wheel.remove(10, |_| true), Some(0));
+        assert_eq!(wheel.min_next_event(), Some(5000));
+        assert_eq!(wheel.remove(5000, |_| true), Some(1));
+        assert_eq!(wheel.min_next_event(), Some(6000));
+        assert_eq!(wheel.pending_count(), 1);
+
+        // Parked by a dropped advance
+        let mut wheel = super::VecDequeWheel::new(0);
+        wheel.schedule(5, 0).unwrap();
+        wheel.schedule(6, 1).unwrap();
+        drop(wheel.advance_to(10));
+        assert_eq!(wheel.remove(6, |_| true), Some(1));
+        assert_eq!(wheel.drain_due().collect::<Vec<_>>(), vec![(0, 5)]);
+    }
+
+    #[test]
+    fn capped_vec_deque() {
+        let mut wheel = super::CappedVecDequeWheel::<usize, 3>::new(0);
+        for i in 0..3 {
+            wheel.schedule(5, i).unwrap();
+        }
+        assert_eq!(wheel.schedule(5, 3), Err(3));
+        wheel.schedule(6, 4).unwrap();
+
+        // Level 1 slots are capped just the same
+        for i in 5..8 {
+            wheel.schedule(100 + i, i).unwrap();
+        }
+        assert_eq!(wheel.schedule(127, 8), Err(8));
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(200, |item, _| fired.push(item));
+        assert_eq!(fired, vec![0, 1, 2, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn count_between() {
+        let mut wheel = super::VecDequeWheel::new(0);
+        wheel.fast_forward(10, |_, _| ());
+
+        let ticks = [10, 12, 12, 70, 100, 127, 128, 5000, 1 << 50];
+        for (i, tick) in ticks.iter().enumerate() {
+            wheel.schedule_far(*tick, i).unwrap();
+        }
+
+        let expected = |from: usize, to: usize| ticks.iter().filter(|t| from <= **t && **t < to).count();
+        let windows = [
+            (0, 10), (10, 11), (10, 13), (12, 12), (13, 64),
+            // Whole level 1 buckets, and parts of them
+            (64, 128), (64, 192), (70, 101), (101, 128), (100, 4096),
+            (4096, 8192), (0, usize::MAX), (1 << 49, 1 << 51),
+        ];
+        for (from, to) in windows.iter() {
+            assert_eq!(wheel.count_between(*from, *to), expected(*from, *to), "[{}, {})", from, to);
+
+            let mut seen = Vec::new();
+            wheel.for_each_between(*from, *to, |item, tick| {
+                assert_eq!(ticks[*item], tick);
+                seen.push(*item);
+            });
+            assert_eq!(seen.len(), expected(*from, *to));
+        }
+
+        // Items parked at the current tick by an interrupted advance keep their own ticks
+        wheel.schedule(11, 100).unwrap();
+        drop(wheel.advance_to(20));
+        assert_eq!(wheel.count_between(20, 21), 0);
+        assert_eq!(wheel.count_between(11, 13), 3);
+        assert_eq!(wheel.count_between(0, usize::MAX), ticks.len() + 1);
+    }
+
+    #[test]
+    fn drain_due() {
+        let mut wheel = super::VecDequeWheel::new(0);
+        wheel.fast_forward(70, |_, _| panic!());
+
+        wheel.schedule(70, 1).unwrap();
+        wheel.schedule(71, 2).unwrap();
+        wheel.schedule(70, 3).unwrap();
+        wheel.schedule(200, 4).unwrap();
+
+        assert_eq!(wheel.drain_due().collect::<Vec<_>>(), vec![(1, 70), (3, 70)]);
+        assert_eq!(wheel.elapsed(), 70);
+        assert_eq!(wheel.drain_due().next(), None);
+        assert_eq!(wheel.min_next_event(), Some(71));
+
+        // Items an interrupted advance parked at the current tick count as due too
+        wheel.schedule(72, 5).unwrap();
+        drop(wheel.advance_to(72));
+        assert_eq!(wheel.drain_due().collect::<Vec<_>>(), vec![(2, 71), (5, 72)]);
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(300, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(4, 200)]);
+    }
+
+    #[test]
+    fn schedule_strict() {
+        let mut wheel = super::VecDequeWheel::new(0);
+        wheel.fast_forward(10, |_, _| panic!());
+
+        assert_eq!(wheel.schedule_strict(9, 1), Err(1));
+        assert_eq!(wheel.schedule_strict(10, 1), Err(1));
+        wheel.schedule_strict(11, 1).unwrap();
+
+        // The lenient one still takes the current tick
+        wheel.schedule(10, 2).unwrap();
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(11, |item, _| fired.push(item));
+        assert_eq!(fired, vec![2, 1]);
+    }
+
+    #[test]
+    fn schedule_after() {
+        let mut wheel = super::VecDequeWheel::new(0);
+
+        assert_eq!(wheel.schedule_after(0, 0), Err(0));
+        assert_eq!(wheel.schedule_after(1 << 48, 0), Err(0));
+
+        wheel.schedule_after(10, 1).unwrap();
+        assert_eq!(wheel.remaining(10), 10);
+
+        wheel.fast_forward(7, |_, _| panic!());
+        assert_eq!(wheel.remaining(10), 3);
+        assert_eq!(wheel.remaining(5), 0);
+
+        wheel.schedule_after(3, 2).unwrap();
+        wheel.schedule_after(60, 3).unwrap();
+        assert_eq!(wheel.schedule_after(0, 4), Err(4));
+        assert_eq!(wheel.schedule_after((1 << 48) - 7, 4), Err(4));
+        wheel.schedule_after((1 << 48) - 8, 4).unwrap();
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(10, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(1, 10), (2, 10)]);
+
+        wheel.schedule_after(1, 5).unwrap();
+        wheel.fast_forward(11, |item, at| fired.push((item, at)));
+        assert_eq!(fired[2..], [(5, 11)]);
+
+        wheel.fast_forward(66, |_, _| panic!());
+        wheel.fast_forward(67, |item, at| fired.push((item, at)));
+        assert_eq!(fired[3..], [(3, 67)]);
+
+        assert_eq!(wheel.min_next_event(), Some(63 << 42));
+        assert_eq!(wheel.remaining((1 << 48) - 1), (1 << 48) - 1 - 67);
+    }
+
+    #[test]
+    fn advance_to() {
+        let mut a = super::VecDequeWheel::new(0);
+        let mut b = super::VecDequeWheel::new(0);
+
+        for (idx, tick) in [5, 5, 6, 63, 64, 65, 89, 100, 129, 4095, 4096, 4097, 300000].iter().enumerate() {
+            a.schedule(*tick, idx).unwrap();
+            b.schedule(*tick, idx).unwrap();
+        }
+
+        for moment in [3, 5, 64, 100, 4096, 4200, 300001].iter() {
+            let mut expected = Vec::new();
+            a.fast_forward(*moment, |item, at| expected.push((item, at)));
+
+            let collected: Vec<_> = b.advance_to(*moment).collect();
+            assert_eq!(collected, expected);
+            assert_eq!(a.min_next_event(), b.min_next_event());
+        }
+
+        assert_eq!(b.min_next_event(), None);
+    }
+
+    #[test]
+    fn advance_to_dropped() {
+        let mut wheel = super::VecDequeWheel::new(0);
+        wheel.schedule(5, 1).unwrap();
+        wheel.schedule(70, 2).unwrap();
+        wheel.schedule(80, 3).unwrap();
+        wheel.schedule(200, 4).unwrap();
+
+        let first: Vec<_> = wheel.advance_to(100).take(1).collect();
+        assert_eq!(first, vec![(1, 5)]);
+        assert_eq!(wheel.elapsed(), 100);
+
+        // The unconsumed ones are due right now
+        assert_eq!(wheel.min_next_event(), Some(100));
+
+        let mut rest = Vec::new();
+        wheel.fast_forward(101, |item, at| rest.push((item, at)));
+        rest.sort();
+        assert_eq!(rest, vec![(2, 70), (3, 80)]);
+
+        assert_eq!(wheel.min_next_event(), Some(192));
+        let last: Vec<_> = wheel.advance_to(300).collect();
+        assert_eq!(last, vec![(4, 200)]);
+    }
+
+    #[test]
+    fn advance_to_dropped_bounded() {
+        // Twice as many due items as a slot holds, some cascading from level 1
+        let mut wheel = super::BoundedWheel::<usize, 4>::new(0);
+        for i in 0..8 {
+            wheel.schedule(i * 10 + 1, i).unwrap();
+        }
+
+        let first: Vec<_> = wheel.advance_to(100).take(1).collect();
+        assert_eq!(first, vec![(0, 1)]);
+        assert_eq!(wheel.pending_count(), 7);
+        assert_eq!(wheel.min_next_event_detailed(), Some((100, 7)));
+        assert_eq!(wheel.peek_next(1).collect::<Vec<_>>(), vec![100]);
+
+        // Dropped again, partway through the parked ones
+        let second: Vec<_> = wheel.advance_to(101).take(2).collect();
+        assert_eq!(second, vec![(1, 11), (2, 21)]);
+        assert_eq!(wheel.pending_count(), 5);
+
+        let rest: Vec<_> = wheel.advance_to(102).collect();
+        assert_eq!(rest, (3..8).map(|i| (i, i * 10 + 1)).collect::<Vec<_>>());
+        assert_eq!(wheel.min_next_event(), None);
+    }
+
+    #[test]
+    fn advance_to_dropped_midway() {
+        // Dropped while only the cascading slot is left. Without alloc, the rest of it goes into the slot of the current tick.
+        let mut wheel = super::BoundedWheel::<usize, 4>::new_bounded(0);
+        for (i, tick) in [70, 75, 80, 110].iter().enumerate() {
+            wheel.schedule(*tick, i).unwrap();
+        }
+
+        let first: Vec<_> = wheel.advance_to(100).take(1).collect();
+        assert_eq!(first, vec![(0, 70)]);
+        assert_eq!(wheel.elapsed(), 100);
+        assert_eq!(wheel.min_next_event_detailed(), Some((100, 2)));
+        assert_eq!(wheel.pending_count(), 3);
+
+        let rest: Vec<_> = wheel.advance_to(120).collect();
+        assert_eq!(rest, vec![(1, 75), (2, 80), (3, 110)]);
+
+        // More due below the cascading slot than a slot holds. Without alloc, they stay where they are.
+        let mut wheel = super::BoundedWheel::<usize, 4>::new_bounded(0);
+        for i in 0..8 {
+            wheel.schedule(i * 10 + 1, i).unwrap();
+        }
+
+        let first: Vec<_> = wheel.advance_to(100).take(1).collect();
+        assert_eq!(first, vec![(0, 1)]);
+        assert_eq!(wheel.elapsed(), 100);
+        assert_eq!(wheel.pending_count(), 7);
+        assert_eq!(wheel.min_next_event_detailed(), Some((100, 7)));
+
+        // Still only what's past the advance can be scheduled
+        assert_eq!(wheel.schedule(99, 8), Err(8));
+        wheel.schedule(100, 8).unwrap();
+        wheel.schedule(101, 9).unwrap();
+        assert_eq!(wheel.advance_to(100).next(), None);
+
+        let rest: Vec<_> = wheel.advance_to(101).collect();
+        let expected: Vec<_> = (1..8).map(|i| (i, i * 10 + 1)).chain(vec![(8, 100), (9, 101)]).collect();
+        assert_eq!(rest, expected);
+        assert_eq!(wheel.elapsed(), 101);
+        assert_eq!(wheel.min_next_event(), None);
+
+        // Same when crossing into a new top-level rotation, the overflow stays scheduled too
+        let mut wheel = super::Wheel::<usize, super::BoundedSlot<(usize, usize), 4>, 1, 6>::new(0);
+        wheel.schedule(5, 0).unwrap();
+        wheel.schedule(6, 1).unwrap();
+        wheel.schedule_far(200, 2).unwrap();
+
+        let first: Vec<_> = wheel.advance_to(100).take(1).collect();
+        assert_eq!(first, vec![(0, 5)]);
+        assert_eq!(wheel.elapsed(), 100);
+        assert_eq!(wheel.sorted_entries(), vec![(6, &1), (200, &2)]);
+
+        let rest: Vec<_> = wheel.advance_to(300).collect();
+        assert_eq!(rest, vec![(1, 6), (2, 200)]);
+    }
+
+    #[cfg(all(feature = "std", feature = "rayon"))]
+    #[test]
+    fn fast_forward_par() {
+        use core::sync::atomic::*;
+
+        let mut wheel = super::VecDequeWheel::<usize>::new(0);
+        for i in 0..1000 {
+            wheel.schedule(i % 300 + 1, i).unwrap();
+        }
+
+        let seen: std::vec::Vec<AtomicUsize> = (0..1000).map(|_| AtomicUsize::new(0)).collect();
+        wheel.fast_forward_par(200, |item, at| {
+            assert_eq!(item % 300 + 1, at);
+            seen[item].fetch_add(1, Ordering::Relaxed);
+        });
+
+        for (i, cnt) in seen.iter().enumerate() {
+            let expected = if i % 300 < 200 { 1 } else { 0 };
+            assert_eq!(cnt.load(Ordering::Relaxed), expected, "Item {}", i);
+        }
+
+        wheel.fast_forward_par(300, |item, _| {
+            seen[item].fetch_add(1, Ordering::Relaxed);
+        });
+        assert!(seen.iter().all(|cnt| cnt.load(Ordering::Relaxed) == 1));
+        assert_eq!(wheel.min_next_event(), None);
+    }
+
+    #[test]
+    fn fast_forward_sorted() {
+        let mut wheel = super::VecDequeWheel::<(usize, u8)>::new(0);
+
+        // (id, priority), spread across levels so some of them get cascaded
+        wheel.schedule(70, (0, 2)).unwrap();
+        wheel.schedule(3, (1, 5)).unwrap();
+        wheel.schedule(70, (2, 1)).unwrap();
+        wheel.schedule(3, (3, 0)).unwrap();
+        wheel.schedule(65, (4, 9)).unwrap();
+        wheel.schedule(70, (5, 1)).unwrap();
+        wheel.schedule(3, (6, 5)).unwrap();
+        wheel.schedule(100, (7, 0)).unwrap();
+
+        let mut fired = Vec::new();
+        wheel.fast_forward_sorted(70, |&(_, prio)| prio, |(id, _), at| fired.push((id, at)));
+        assert_eq!(fired, vec![(3, 3), (1, 3), (6, 3), (4, 65), (2, 70), (5, 70), (0, 70)]);
+
+        fired.clear();
+        wheel.fast_forward_sorted(100, |&(_, prio)| prio, |(id, _), at| fired.push((id, at)));
+        assert_eq!(fired, vec![(7, 100)]);
+    }
+
+    #[test]
+    fn advance_if_due() {
+        let mut wheel = super::VecDequeWheel::<usize>::new(0);
+        let mut fired = Vec::new();
+
+        // Nothing scheduled, even a jump across levels is just a store
+        assert!(wheel.crosses_level(100));
+        assert!(!wheel.advance_if_due(100, |item, at| fired.push((item, at))));
+        assert_eq!(wheel.elapsed(), 100);
+
+        wheel.schedule(5000, 0).unwrap();
+        wheel.schedule(130, 1).unwrap();
+        assert!(!wheel.advance_if_due(127, |item, at| fired.push((item, at))));
+        assert_eq!(wheel.level_histogram()[..3], [0, 1, 1]);
+
+        // Due, so the slot holding 130 is cascaded and fired
+        assert!(wheel.advance_if_due(200, |item, at| fired.push((item, at))));
+        assert_eq!(fired, vec![(1, 130)]);
+
+        assert!(!wheel.advance_if_due(4095, |item, at| fired.push((item, at))));
+        assert!(wheel.advance_if_due(4096, |item, at| fired.push((item, at))));
+        assert_eq!(wheel.level_histogram()[..3], [0, 1, 0]);
+        assert!(wheel.advance_if_due(5000, |item, at| fired.push((item, at))));
+        assert_eq!(fired, vec![(1, 130), (0, 5000)]);
+
+        // Not due yet, but the new rotation has to pull it out of the overflow
+        let next_rotation = wheel.horizon() + 1;
+        wheel.schedule_far(next_rotation + 10, 2).unwrap();
+        assert!(wheel.advance_if_due(next_rotation, |item, at| fired.push((item, at))));
+        assert_eq!(wheel.sorted_entries(), vec![(next_rotation + 10, &2)]);
+        assert_eq!(wheel.level_histogram()[0], 1);
+        assert!(wheel.advance_if_due(next_rotation + 10, |item, at| fired.push((item, at))));
+        assert_eq!(fired.len(), 3);
+    }
+
+    #[test]
+    fn from_events() {
+        let wheel = super::VecDequeWheel::<usize>::from_events(100, vec![(300, 0), (101, 1), (1 << 50, 2), (300, 3)]).unwrap();
+        assert_eq!(wheel.sorted_entries(), vec![(101, &1), (300, &0), (300, &3), (1 << 50, &2)]);
+
+        let err = super::VecDequeWheel::<usize>::from_events(100, vec![(300, 0), (99, 1)]).err();
+        assert_eq!(err, Some((99, 1)));
+
+        let err = super::BoundedWheel::<usize, 1>::from_events(0, vec![(5, 0), (5, 1)]).err();
+        assert_eq!(err, Some((5, 1)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let mut wheel = super::VecDequeWheel::<String>::new(0);
+        for (i, tick) in [5000, 70, 4200, 70, 1 << 49, 131].iter().enumerate() {
+            wheel.schedule_far(*tick, format!("item {}", i)).unwrap();
+        }
+        wheel.fast_forward(100, |_, _| ());
+
+        let saved = serde_json::to_string(&wheel).unwrap();
+        let mut restored: super::VecDequeWheel<String> = serde_json::from_str(&saved).unwrap();
+        assert_eq!(restored.elapsed(), 100);
+        assert_eq!(restored.min_next_event_detailed(), wheel.min_next_event_detailed());
+        assert_eq!(restored.level_histogram(), wheel.level_histogram());
+
+        let mut expected = Vec::new();
+        wheel.fast_forward((1 << 49) + 1, |item, at| expected.push((item, at)));
+        let mut fired = Vec::new();
+        restored.fast_forward((1 << 49) + 1, |item, at| fired.push((item, at)));
+        assert_eq!(expected.len(), 4);
+        assert_eq!(fired, expected);
+
+        // Tampered with, an event before elapsed
+        let tampered = r#"{"elapsed":100,"events":[[200,"late"],[99,"early"]]}"#;
+        let err = serde_json::from_str::<super::VecDequeWheel<String>>(tampered).err().unwrap();
+        assert!(err.to_string().contains("tick 99"));
+
+        // Same-tick items of a LIFO wheel still fire newest first
+        let mut wheel = super::LifoWheel::<usize>::new(0);
+        for (i, tick) in [5, 5, 5, 70, 70, 80, 80].iter().enumerate() {
+            wheel.schedule(*tick, i).unwrap();
+        }
+        let saved = serde_json::to_string(&wheel).unwrap();
+        let mut restored: super::LifoWheel<usize> = serde_json::from_str(&saved).unwrap();
+        assert_eq!(restored.sorted_entries(), wheel.sorted_entries());
+
+        let mut expected = Vec::new();
+        let mut fired = Vec::new();
+        for moment in [10, 70, 100].iter() {
+            wheel.fast_forward(*moment, |item, _| expected.push(item));
+            restored.fast_forward(*moment, |item, _| fired.push(item));
+        }
+        assert_eq!(expected, vec![2, 1, 0, 4, 3, 6, 5]);
+        assert_eq!(fired, expected);
+
+        // Or a parked one that isn't due yet
+        let tampered = r#"{"elapsed":100,"events":[],"parked":[[101,"early"]]}"#;
+        let err = serde_json::from_str::<super::VecDequeWheel<String>>(tampered).err().unwrap();
+        assert!(err.to_string().contains("tick 101"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_parked() {
+        let mut wheel = super::VecDequeWheel::<usize>::new(0);
+        for (i, tick) in [5, 70, 80, 80, 90, 200].iter().enumerate() {
+            wheel.schedule(*tick, i).unwrap();
+        }
+
+        // Everything up to 100 but the first is parked, at ticks before elapsed
+        let first: Vec<_> = wheel.advance_to(100).take(1).collect();
+        assert_eq!(first, vec![(0, 5)]);
+        assert_eq!(wheel.min_next_event_detailed(), Some((100, 4)));
+
+        let saved = serde_json::to_string(&wheel).unwrap();
+        let mut restored: super::VecDequeWheel<usize> = serde_json::from_str(&saved).unwrap();
+        assert_eq!(restored.elapsed(), 100);
+        assert_eq!(restored.min_next_event_detailed(), wheel.min_next_event_detailed());
+        assert_eq!(restored.sorted_entries(), wheel.sorted_entries());
+
+        let mut expected = Vec::new();
+        wheel.fast_forward(300, |item, at| expected.push((item, at)));
+        let mut fired = Vec::new();
+        restored.fast_forward(300, |item, at| fired.push((item, at)));
+        assert_eq!(expected, vec![(1, 70), (2, 80), (3, 80), (4, 90), (5, 200)]);
+        assert_eq!(fired, expected);
+    }
+
+    #[test]
+    fn panic_safety() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        use std::rc::Rc;
+
+        let alive = Rc::new(());
+        let schedule = |wheel: &mut super::VecDequeWheel<(usize, Rc<()>)>| {
+            for (i, tick) in [5, 70, 5, 4100, 300].iter().enumerate() {
+                wheel.schedule(*tick, (i, alive.clone())).unwrap();
+            }
+        };
+
+        // Panics on the second item, halfway through cascading
+        let mut wheel = super::VecDequeWheel::new(0);
+        schedule(&mut wheel);
+        let mut fired = Vec::new();
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            wheel.fast_forward(1000, |(i, _), _| {
+                if fired.len() == 1 {
+                    panic!("callback failed");
+                }
+                fired.push(i);
+            });
+        }));
+        assert!(result.is_err());
+        assert_eq!(wheel.elapsed(), 1000);
+        // The one it panicked on is gone, the rest is still in there
+        assert_eq!(Rc::strong_count(&alive), 4);
+        assert_eq!(wheel.pending_count(), 3);
+
+        wheel.fast_forward(5000, |(i, _), _| fired.push(i));
+        assert_eq!(fired.len(), 4);
+        assert!(fired.contains(&3));
+        assert_eq!(Rc::strong_count(&alive), 1);
+
+        let mut wheel = super::VecDequeWheel::new(0);
+        schedule(&mut wheel);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            wheel.fast_forward_sorted(1000, |(i, _)| *i, |(i, _), _| {
+                if i == 2 {
+                    panic!("callback failed");
+                }
+            });
+        }));
+        assert!(result.is_err());
+        assert_eq!(wheel.sorted_entries().iter().map(|(tick, (i, _))| (*tick, *i)).collect::<Vec<_>>(), vec![(70, 1), (300, 4), (4100, 3)]);
+        drop(wheel);
+        assert_eq!(Rc::strong_count(&alive), 1);
+
+        // Bounded slots drop what's left in them along with the wheel
+        let mut wheel = super::BoundedWheel::<Rc<()>, 4>::new_bounded(0);
+        for tick in [3, 3, 200, 1 << 20].iter() {
+            wheel.schedule(*tick, alive.clone()).unwrap();
+        }
+        wheel.fast_forward(100, |_, _| ());
+        assert_eq!(Rc::strong_count(&alive), 3);
+        drop(wheel);
+        assert_eq!(Rc::strong_count(&alive), 1);
+        // Panics on a wheel with more due than a bounded slot holds
+        let mut wheel = super::BoundedWheel::<(usize, Rc<()>), 4>::new_bounded(0);
+        for i in 0..4 {
+            wheel.schedule(7, (i, alive.clone())).unwrap();
+            wheel.schedule(100, (i + 4, alive.clone())).unwrap();
+        }
+        let mut fired = Vec::new();
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            wheel.fast_forward(200, |(i, _), _| {
+                if i == 1 {
+                    panic!("callback failed");
+                }
+                fired.push(i);
+            });
+        }));
+        assert!(result.is_err());
+        assert_eq!(fired, vec![0]);
+        assert_eq!(wheel.elapsed(), 200);
+        assert_eq!(wheel.pending_count(), 6);
+        assert_eq!(Rc::strong_count(&alive), 7);
+
+        wheel.fast_forward(201, |(i, _), _| fired.push(i));
+        assert_eq!(fired, vec![0, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(Rc::strong_count(&alive), 1);
+
+        // Panics on the cascading slot, without alloc the rest of it fits into the slot of the current tick
+        let mut wheel = super::BoundedWheel::<(usize, Rc<()>), 4>::new_bounded(0);
+        for (i, tick) in [70, 75, 80, 110].iter().enumerate() {
+            wheel.schedule(*tick, (i, alive.clone())).unwrap();
+        }
+        let mut fired = Vec::new();
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            wheel.fast_forward(100, |(i, _), _| {
+                if i == 1 {
+                    panic!("callback failed");
+                }
+                fired.push(i);
+            });
+        }));
+        assert!(result.is_err());
+        assert_eq!(wheel.elapsed(), 100);
+        assert_eq!(wheel.pending_count(), 2);
+        assert_eq!(Rc::strong_count(&alive), 3);
+
+        wheel.fast_forward(200, |(i, _), _| fired.push(i));
+        assert_eq!(fired, vec![0, 2, 3]);
+        drop(wheel);
+        assert_eq!(Rc::strong_count(&alive), 1);
+    }
+
+    #[test]
+    fn sorted_entries() {
+        let mut wheel = super::VecDequeWheel::<usize>::new(0);
+        assert!(wheel.sorted_entries().is_empty());
+
+        wheel.schedule(200, 0).unwrap();
+        wheel.schedule(70, 1).unwrap();
+        wheel.schedule(200, 2).unwrap();
+        wheel.schedule(3, 3).unwrap();
+        wheel.schedule_far(1 << 40, 4).unwrap();
+        assert_eq!(wheel.sorted_entries(), vec![(3, &3), (70, &1), (200, &0), (200, &2), (1 << 40, &4)]);
+
+        // At 192 the level 1 bucket of 200 cascades down to level 0, the one scheduled after that still comes last
+        wheel.fast_forward(192, |_, _| ());
+        wheel.schedule(200, 5).unwrap();
+        wheel.schedule(193, 6).unwrap();
+        assert_eq!(wheel.sorted_entries(), vec![(193, &6), (200, &0), (200, &2), (200, &5), (1 << 40, &4)]);
+
+        // Nothing was taken out
+        assert_eq!(wheel.pending_count(), 5);
+        let mut fired = Vec::new();
+        wheel.fast_forward(300, |item, at| fired.push((at, item)));
+        assert_eq!(fired, vec![(193, 6), (200, 0), (200, 2), (200, 5)]);
+    }
+
+    #[test]
+    fn occupied_ticks() {
+        let mut wheel = super::VecDequeWheel::<usize>::new(0);
+        assert_eq!(wheel.occupied_ticks(), Vec::<usize>::new());
+
+        wheel.schedule(5, 0).unwrap();
+        wheel.schedule(200, 1).unwrap();
+        wheel.schedule(5, 2).unwrap();
+        wheel.schedule(89, 3).unwrap();
+        assert_eq!(wheel.occupied_ticks(), vec![5, 89, 200]);
+
+        wheel.schedule_far(1 << 50, 4).unwrap();
+        wheel.fast_forward(5, |_, _| ());
+        assert_eq!(wheel.occupied_ticks(), vec![89, 200, 1 << 50]);
+
+        let mut bounded = super::BoundedWheel::<usize, 4>::new_bounded(0);
+        bounded.schedule(70, 0).unwrap();
+        bounded.schedule(65, 1).unwrap();
+        bounded.schedule(70, 2).unwrap();
+        assert_eq!(bounded.occupied_ticks(), vec![65, 70]);
+    }
+
+    #[test]
+    fn same_tick_order() {
+        use super::SlotLike;
+
+        let mut bounded = super::BoundedWheel::<usize, 8>::new_bounded(0);
+        let mut vecdeque = super::VecDequeWheel::<usize>::new(0);
+
+        // Some on the first level, some cascading down from higher ones
+        for (idx, &tick) in [3, 3, 200, 3, 200, 200, 5000, 5000].iter().enumerate() {
+            bounded.schedule(tick, idx).unwrap();
+            vecdeque.schedule(tick, idx).unwrap();
+        }
+
+        let mut from_bounded = Vec::new();
+        let mut from_vecdeque = Vec::new();
+        bounded.fast_forward(5000, |item, at| from_bounded.push((item, at)));
+        vecdeque.fast_forward(5000, |item, at| from_vecdeque.push((item, at)));
+
+        assert_eq!(from_bounded, from_vecdeque);
+        assert_eq!(from_bounded, vec![(0, 3), (1, 3), (3, 3), (2, 200), (4, 200), (5, 200), (6, 5000), (7, 5000)]);
+
+        // Stays FIFO once the ring buffer wraps around
+        let mut slot = super::BoundedSlot::<usize, 3>::new();
+        for round in 0..4 {
+            slot.push(round * 2).unwrap();
+            slot.push(round * 2 + 1).unwrap();
+            assert_eq!(slot.pop(), Some(round * 2));
+            assert_eq!(slot.pop(), Some(round * 2 + 1));
+        }
+    }
+
+    #[test]
+    fn run_due() {
+        use std::sync::{Arc, Mutex};
+
+        let mut wheel = super::VecDequeWheel::<super::Task>::new(0);
+        let ran = Arc::new(Mutex::new(Vec::new()));
+
+        for &(tick, name) in [(300, "c"), (5, "a"), (70, "b"), (5, "a2"), (4100, "d")].iter() {
+            let ran = ran.clone();
+            wheel.schedule_fn(tick, move || ran.lock().unwrap().push((tick, name))).map_err(|_| ()).unwrap();
+        }
+
+        wheel.run_due(4);
+        assert!(ran.lock().unwrap().is_empty());
+
+        wheel.run_due(100);
+        assert_eq!(*ran.lock().unwrap(), vec![(5, "a"), (5, "a2"), (70, "b")]);
+
+        wheel.run_due(5000);
+        assert_eq!(*ran.lock().unwrap(), vec![(5, "a"), (5, "a2"), (70, "b"), (300, "c"), (4100, "d")]);
+        assert_eq!(wheel.min_next_event(), None);
+    }
+
+    #[test]
+    fn pending() {
+        let mut wheel = super::VecDequeWheel::<usize>::new(0);
+        assert_eq!(wheel.pending_count(), 0);
+        assert_eq!(wheel.peek_next(4).count(), 0);
+
+        for (idx, &tick) in [5, 5, 9, 70, 100, 4100, 1 << 50].iter().enumerate() {
+            if tick > wheel.horizon() {
+                wheel.schedule_far(tick, idx).unwrap();
+            } else {
+                wheel.schedule(tick, idx).unwrap();
+            }
+        }
+        assert_eq!(wheel.pending_count(), 7);
+
+        // 70 and 100 share a level 1 slot starting at 64, 4100 sits in the level 2 slot starting at 4096
+        let next: Vec<usize> = wheel.peek_next(10).collect();
+        assert_eq!(next, vec![5, 9, 64, 4096, 1 << 50]);
+        assert_eq!(wheel.peek_next(2).collect::<Vec<_>>(), vec![5, 9]);
+        assert_eq!(wheel.peek_next(1).next(), wheel.min_next_event());
+
+        wheel.fast_forward(80, |_, _| ());
+        assert_eq!(wheel.pending_count(), 3);
+        assert_eq!(wheel.peek_next(10).collect::<Vec<_>>(), vec![100, 4096, 1 << 50]);
+    }
+
+    #[test]
+    fn clear() {
+        use std::rc::Rc;
+
+        let alive = Rc::new(());
+        let mut wheel = super::BoundedWheel::<Rc<()>, 4>::new_bounded(0);
+
+        for &tick in [0, 5, 5, 70, 4100, 1 << 40].iter() {
+            wheel.schedule(tick, alive.clone()).unwrap();
+        }
+        wheel.schedule_far(1 << 50, alive.clone()).unwrap();
+        assert_eq!(Rc::strong_count(&alive), 8);
+
+        wheel.clear();
+        assert_eq!(Rc::strong_count(&alive), 1);
+        assert_eq!(wheel.pending_count(), 0);
+        assert_eq!(wheel.min_next_event(), None);
+        assert_eq!(wheel.elapsed(), 0);
+
+        wheel.schedule(10, alive.clone()).unwrap();
+        wheel.reset_to(1000);
+        assert_eq!(Rc::strong_count(&alive), 1);
+        assert_eq!(wheel.elapsed(), 1000);
+
+        // Usable from the new position
+        wheel.schedule(1010, alive.clone()).unwrap();
+        assert_eq!(wheel.min_next_event(), Some(1010));
+        let mut fired = 0;
+        wheel.fast_forward(2000, |_, at| {
+            assert_eq!(at, 1010);
+            fired += 1;
+        });
+        assert_eq!(fired, 1);
+        assert_eq!(Rc::strong_count(&alive), 1);
+    }
+
+    #[test]
+    fn debug() {
+        struct Opaque;
+
+        let mut wheel = super::VecDequeWheel::new(3);
+        wheel.schedule(10, Opaque).ok().unwrap();
+        assert_eq!(format!("{:?}", wheel), "Wheel { elapsed: 3, min_next_event: Some(10), pending_count: 1 }");
+    }
+
+    #[test]
+    fn priority() {
+        let mut wheel = super::PriorityWheel::<&str>::new(0);
+        wheel.schedule_with_priority(5, 3, "c").unwrap();
+        wheel.schedule_with_priority(5, 1, "a").unwrap();
+        wheel.schedule_with_priority(5, 2, "b").unwrap();
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(5, |p, at| fired.push((p.item, at)));
+        assert_eq!(fired, vec![("a", 5), ("b", 5), ("c", 5)]);
+
+        // Across ticks the order is still by tick first, also when cascading out of higher levels
+        wheel.schedule_with_priority(100, 0, "late").unwrap();
+        wheel.schedule_with_priority(90, 9, "early").unwrap();
+        wheel.schedule_with_priority(90, 4, "earliest").unwrap();
+
+        fired.clear();
+        wheel.fast_forward(200, |p, at| fired.push((p.item, at)));
+        assert_eq!(fired, vec![("earliest", 90), ("early", 90), ("late", 100)]);
+    }
+
+    #[test]
+    fn crosses_level() {
+        let mut wheel = super::VecDequeWheel::<usize>::new(10);
+
+        assert!(!wheel.crosses_level(10));
+        assert!(!wheel.crosses_level(11));
+        assert!(!wheel.crosses_level(63));
+        assert!(wheel.crosses_level(64));
+        assert!(wheel.crosses_level(5000));
+        assert!(wheel.crosses_level(usize::MAX));
+
+        wheel.fast_forward(64, |_, _| ());
+        assert!(!wheel.crosses_level(127));
+        assert!(wheel.crosses_level(128));
+        assert!(!wheel.crosses_level(3));
+    }
+
+    #[test]
+    fn level_fill() {
+        let mut bounded = super::BoundedWheel::<usize, 4>::new_bounded(0);
+        let mut unbounded = super::VecDequeWheel::<usize>::new(0);
+
+        // Mostly clustered within the first 64 ticks, a few further out
+        let ticks = (1..40).chain((1..40).step_by(2)).chain([100, 5000].iter().cloned());
+        for (idx, tick) in ticks.enumerate() {
+            bounded.schedule(tick, idx).unwrap();
+            unbounded.schedule(tick, idx).unwrap();
+        }
+
+        assert_eq!(bounded.level_histogram()[..3], [59, 1, 1]);
+        assert_eq!(unbounded.level_histogram(), bounded.level_histogram());
+
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+
+        let bounded_fill = bounded.level_fill_ratio();
+        assert!(close(bounded_fill[0], 59.0 / 256.0));
+        assert!(close(bounded_fill[1], 1.0 / 256.0));
+        assert!(bounded_fill[3..].iter().all(|&r| close(r, 0.0)));
+
+        let unbounded_fill = unbounded.level_fill_ratio();
+        assert!(close(unbounded_fill[0], 39.0 / 64.0));
+        assert!(close(unbounded_fill[2], 1.0 / 64.0));
+        assert!(unbounded_fill[0] > unbounded_fill[1]);
+    }
+
+    #[test]
+    fn min_next_event_detailed() {
+        let mut wheel = super::VecDequeWheel::<usize>::new(0);
+        assert_eq!(wheel.min_next_event_detailed(), None);
+
+        for i in 0..3 {
+            wheel.schedule(7, i).unwrap();
+        }
+        wheel.schedule(8, 3).unwrap();
+        assert_eq!(wheel.min_next_event_detailed(), Some((7, 3)));
+
+        // 70 and 100 share the level 1 slot starting at 64, the count covers both
+        wheel.fast_forward(10, |_, _| ());
+        wheel.schedule(70, 4).unwrap();
+        wheel.schedule(100, 5).unwrap();
+        assert_eq!(wheel.min_next_event_detailed(), Some((64, 2)));
+    }
+
+    #[test]
+    fn out_of_range() {
+        let mut wheel = super::VecDequeWheel::<usize>::new(0);
+        let boundary = (1 << 48) - 1;
+
+        assert_eq!(wheel.horizon(), boundary);
+        wheel.schedule(boundary, 0).unwrap();
+        assert_eq!(wheel.schedule(boundary + 1, 1), Err(1));
+        assert_eq!(wheel.schedule(usize::MAX, 1), Err(1));
+
+        // Too far for the levels, so they wait in the overflow slot
+        wheel.schedule_far(boundary + 1, 1).unwrap();
+        wheel.schedule_far(boundary + 10, 2).unwrap();
+        wheel.schedule_far((1 << 49) + 5, 3).unwrap();
+        wheel.schedule_far(5, 4).unwrap();
+        assert_eq!(wheel.schedule_far(3, 5), Ok(()));
+        assert_eq!(wheel.min_next_event(), Some(3));
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(boundary - 1, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(5, 3), (4, 5)]);
+        assert_eq!(wheel.min_next_event(), Some(boundary));
+
+        // Only overflowed items left, min_next_event still sees them
+        wheel.fast_forward(boundary, |item, at| fired.push((item, at)));
+        assert_eq!(fired[2..], [(0, boundary)]);
+        assert_eq!(wheel.min_next_event(), Some(boundary + 1));
+
+        // Rolling over the top level moves the ones now in range into the levels
+        wheel.fast_forward(boundary + 1, |item, at| fired.push((item, at)));
+        assert_eq!(fired[3..], [(1, boundary + 1)]);
+        assert_eq!(wheel.horizon(), (1 << 49) - 1);
+        assert_eq!(wheel.min_next_event(), Some(boundary + 10));
+
+        wheel.schedule_far((1 << 50) + 100, 6).unwrap();
+        wheel.fast_forward((1 << 49) + 5, |item, at| fired.push((item, at)));
+        assert_eq!(fired[4..], [(2, boundary + 10), (3, (1 << 49) + 5)]);
+        assert_eq!(wheel.min_next_event(), Some((1 << 50) + 100));
+
+        // Jumping into the rotation of an overflowed item that isn't due yet
+        wheel.fast_forward(1 << 50, |_, _| panic!());
+        assert_eq!(wheel.min_next_event(), Some((1 << 50) + 64));
+        wheel.fast_forward((1 << 50) + 100, |item, at| fired.push((item, at)));
+        assert_eq!(fired[6..], [(6, (1 << 50) + 100)]);
+        assert_eq!(wheel.min_next_event(), None);
+    }
+
+    #[test]
+    fn overflow_migration() {
+        // Two levels, so the horizon is the end of the current 4096 tick rotation
+        let mut wheel = super::Wheel::<usize, std::collections::VecDeque<(usize, usize)>, 2, 6>::new(0);
+        assert_eq!(wheel.horizon(), 4095);
+        wheel.schedule_far(10000, 0).unwrap();
+        wheel.schedule_far(5000, 1).unwrap();
+        assert_eq!(wheel.level_histogram(), [0, 0]);
+
+        // Still in the overflow slot, reported exactly
+        assert_eq!(wheel.min_next_event(), Some(5000));
+        wheel.fast_forward(4095, |_, _| panic!());
+        assert_eq!(wheel.min_next_event(), Some(5000));
+
+        // The next rotation takes 5000 into level 1, which reports the start of its bucket like for any other item
+        wheel.fast_forward(4096, |_, _| panic!());
+        assert_eq!(wheel.level_histogram(), [0, 1]);
+        assert_eq!(wheel.min_next_event(), Some(4992));
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(8191, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(1, 5000)]);
+        assert_eq!(wheel.min_next_event(), Some(10000));
+
+        wheel.fast_forward(8192, |_, _| panic!());
+        assert_eq!(wheel.level_histogram(), [0, 1]);
+        assert_eq!(wheel.min_next_event(), Some(9984));
+        wheel.fast_forward(10000, |item, at| fired.push((item, at)));
+        assert_eq!(fired[1..], [(0, 10000)]);
+        assert_eq!(wheel.min_next_event(), None);
+    }
+
+    #[test]
+    fn spare_slot() {
+        let mut wheel = super::VecDequeWheel::<usize>::new(0);
+        assert!(wheel.spare.is_none());
+
+        for i in 0..4 {
+            wheel.schedule(64 + i * 20, i).unwrap();
+        }
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(64, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(0, 64)]);
+        assert!(wheel.spare.is_some());
+
+        // The next cascade takes the spare, and hands back the slot it drained
+        wheel.schedule(130, 4).unwrap();
+        wheel.fast_forward(128, |item, at| fired.push((item, at)));
+        assert!(wheel.spare.is_some());
+        wheel.fast_forward(130, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(0, 64), (1, 84), (2, 104), (3, 124), (4, 130)]);
+        assert_eq!(wheel.min_next_event(), None);
+
+        // Dropping the iterator early still recycles the slot once it's drained
+        wheel.schedule(200, 5).unwrap();
+        wheel.schedule(200, 6).unwrap();
+        wheel.spare = None;
+        {
+            let mut advance = wheel.advance_to(200);
+            assert!(advance.next().is_some());
+        }
+        assert!(wheel.spare.is_some());
+        assert_eq!(wheel.min_next_event(), Some(200));
+    }
+
     #[test]
     fn random() {
         use rand_distr::*;
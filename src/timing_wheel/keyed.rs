@@ -0,0 +1,128 @@
+use super::hierarchical::SlotLike;
+use super::slab::{RescheduleError, SlabKey, SlabWheel, TimerToken};
+
+use alloc::collections::BTreeMap;
+
+// At most one pending timer per key, wherever it's scheduled. Scheduling a key again replaces
+//   its pending timer, which is the usual debounce pattern.
+pub struct KeyedWheel<K: Ord + Clone, T, S: SlotLike<Item = (SlabKey, usize)>, const LEVEL: usize, const CUTOFF: usize> {
+    wheel: SlabWheel<(K, T), S, LEVEL, CUTOFF>,
+    keys: BTreeMap<K, TimerToken>,
+}
+
+impl<K: Ord + Clone, T, S: SlotLike<Item = (SlabKey, usize)>, const LEVEL: usize, const CUTOFF: usize> KeyedWheel<K, T, S, LEVEL, CUTOFF> {
+    pub fn new(elapsed: usize) -> Self {
+        Self {
+            wheel: SlabWheel::new(elapsed),
+            keys: BTreeMap::new(),
+        }
+    }
+
+    pub fn elapsed(&self) -> usize {
+        self.wheel.elapsed()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.keys.contains_key(key)
+    }
+
+    // Returns the item of the timer it replaced, if the key had one pending.
+    // If the new one is rejected, the pending timer is left alone.
+    pub fn schedule_keyed(&mut self, key: K, tick: usize, i: T) -> Result<Option<T>, T> {
+        // Moved rather than scheduled anew, so the replaced timer doesn't stay behind in its slot
+        if let Some(token) = self.keys.get(&key) {
+            match self.wheel.reschedule(*token, tick) {
+                Ok(()) => {
+                    let (_, pending) = self.wheel.get_mut(*token).unwrap();
+                    return Ok(Some(core::mem::replace(pending, i)));
+                }
+                Err(RescheduleError::Rejected) => return Err(i),
+                // Fired timers take their key along, see fast_forward
+                Err(RescheduleError::Gone) => unreachable!(),
+            }
+        }
+
+        let token = self.wheel.schedule(tick, (key.clone(), i)).map_err(|(_, i)| i)?;
+        self.keys.insert(key, token);
+        Ok(None)
+    }
+
+    pub fn cancel_key(&mut self, key: &K) -> Option<T> {
+        let token = self.keys.remove(key)?;
+        self.wheel.cancel(token).map(|(_, i)| i)
+    }
+
+    pub fn fast_forward<F: FnMut(K, T, usize)>(&mut self, moment: usize, mut f: F) {
+        let keys = &mut self.keys;
+
+        self.wheel.fast_forward(moment, |(key, i), ts| {
+            // Replaced timers never fire, so this is the one the key points to
+            keys.remove(&key);
+            f(key, i, ts);
+        });
+    }
+
+    pub fn min_next_event(&self) -> Option<usize> {
+        self.wheel.min_next_event()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SlabKey;
+    use crate::timing_wheel::hierarchical::BoundedSlot;
+    use std::collections::VecDeque;
+
+    type Wheel = super::KeyedWheel<&'static str, usize, VecDeque<(SlabKey, usize)>, 8, 6>;
+
+    #[test]
+    fn keyed() {
+        let mut wheel = Wheel::new(0);
+
+        assert_eq!(wheel.schedule_keyed("a", 10, 1), Ok(None));
+        assert_eq!(wheel.schedule_keyed("b", 15, 2), Ok(None));
+        assert_eq!(wheel.schedule_keyed("a", 20, 3), Ok(Some(1)));
+
+        // A rejected schedule leaves the pending one in place
+        assert_eq!(wheel.schedule_keyed("b", 1 << 48, 4), Err(4));
+        assert!(wheel.contains_key(&"b"));
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(12, |key, item, at| fired.push((key, item, at)));
+        assert!(fired.is_empty());
+
+        wheel.fast_forward(30, |key, item, at| fired.push((key, item, at)));
+        assert_eq!(fired, vec![("b", 2, 15), ("a", 3, 20)]);
+        assert!(!wheel.contains_key(&"a"));
+
+        wheel.schedule_keyed("a", 40, 5).unwrap();
+        wheel.schedule_keyed("c", 40, 6).unwrap();
+        assert_eq!(wheel.cancel_key(&"a"), Some(5));
+        assert_eq!(wheel.cancel_key(&"a"), None);
+
+        wheel.fast_forward(50, |key, item, at| fired.push((key, item, at)));
+        assert_eq!(fired[2..], [("c", 6, 40)]);
+        assert_eq!(wheel.min_next_event(), None);
+    }
+
+    #[test]
+    fn replaced_in_bounded_slot() {
+        let mut wheel = super::KeyedWheel::<&'static str, usize, BoundedSlot<(SlabKey, usize), 2>, 8, 6>::new(0);
+        wheel.schedule_keyed("b", 10, 0).unwrap();
+
+        // Replaced in the full slot more times than it holds, the replaced timers don't stay behind
+        assert_eq!(wheel.schedule_keyed("a", 10, 1), Ok(None));
+        for i in 2..6 {
+            assert_eq!(wheel.schedule_keyed("a", 10, i), Ok(Some(i - 1)));
+        }
+
+        // Pushed out, nothing of it is left at 10
+        assert_eq!(wheel.schedule_keyed("b", 20, 6), Ok(Some(0)));
+        assert_eq!(wheel.schedule_keyed("a", 30, 7), Ok(Some(5)));
+        assert_eq!(wheel.min_next_event(), Some(20));
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(50, |key, item, at| fired.push((key, item, at)));
+        assert_eq!(fired, vec![("b", 6, 20), ("a", 7, 30)]);
+    }
+}
@@ -1 +1,14 @@
 pub mod hierarchical;
+pub mod driver;
+#[cfg(any(feature = "alloc", test))]
+pub mod sticky;
+#[cfg(any(feature = "alloc", test))]
+pub mod slab;
+#[cfg(any(feature = "alloc", test))]
+pub mod keyed;
+#[cfg(any(feature = "alloc", test))]
+pub mod concurrent;
+
+// The wheel and the pieces needed to back it with a slot of your own, see Wheel
+pub use hierarchical::{BoundedSlot, BoundedWheel, SlotLike, SlotOrder, SlotWheel, Wheel};
+pub use driver::WheelDriver;
@@ -0,0 +1,553 @@
+use super::hierarchical::{SlotLike, Wheel};
+
+use alloc::vec::Vec;
+
+// Items live in a slab beside the wheel, the wheel itself only holds keys into it.
+// Rescheduling bumps the entry's stamp, schedules a new key and takes the old one out of its slot.
+// Cancelling takes the key out of its slot too, and frees the entry. The stamp moves along with it,
+//   so a key that can't be taken out right away, because the wheel is being advanced, is skipped once it comes due.
+// A group is a set of timers sharing one item, cloned into each. Cancelling goes through the members' entries,
+//   so it reaches copies on whatever level they have cascaded to.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimerToken {
+    idx: usize,
+    generation: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GroupToken {
+    idx: usize,
+    generation: usize,
+}
+
+// What the wheel actually stores
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlabKey {
+    idx: usize,
+    stamp: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RescheduleError {
+    // Already fired, or the token is stale
+    Gone,
+    // The new tick is in the past, out of range, or its slot is full. The timer stays where it was.
+    Rejected,
+}
+
+struct Entry<T> {
+    generation: usize,
+    stamp: usize,
+    // Where the current key is scheduled, to find it again
+    tick: usize,
+    item: Option<T>,
+    // Index into groups, for copies scheduled by schedule_group
+    group: Option<usize>,
+}
+
+struct Group {
+    generation: usize,
+    members: Vec<TimerToken>,
+    // Members not yet fired or cancelled. The group is released along with the last of them.
+    live: usize,
+}
+
+// Everything but the wheel, so the fire callbacks can borrow it while the wheel is being advanced
+struct Slab<T> {
+    entries: Vec<Entry<T>>,
+    free: Vec<usize>,
+    groups: Vec<Group>,
+    free_groups: Vec<usize>,
+}
+
+impl<T> Slab<T> {
+    fn alloc(&mut self) -> usize {
+        match self.free.pop() {
+            Some(idx) => idx,
+            None => {
+                self.entries.push(Entry {
+                    generation: 0,
+                    stamp: 0,
+                    tick: 0,
+                    item: None,
+                    group: None,
+                });
+                self.entries.len() - 1
+            }
+        }
+    }
+
+    // Free the entry for reuse. The stamp moves too, so a reused entry never matches an old key.
+    fn release(&mut self, idx: usize) -> Option<T> {
+        let entry = &mut self.entries[idx];
+        entry.generation = entry.generation.wrapping_add(1);
+        entry.stamp = entry.stamp.wrapping_add(1);
+        let item = entry.item.take();
+        let group = entry.group.take();
+        self.free.push(idx);
+
+        if let Some(g) = group {
+            let group = &mut self.groups[g];
+            group.live -= 1;
+            if group.live == 0 {
+                group.generation = group.generation.wrapping_add(1);
+                group.members.clear();
+                self.free_groups.push(g);
+            }
+        }
+
+        item
+    }
+
+    // Where the key of a pending timer is scheduled, None if it already fired or was cancelled
+    fn key(&self, token: TimerToken) -> Option<(usize, SlabKey)> {
+        match self.entries.get(token.idx) {
+            Some(entry) if entry.generation == token.generation && entry.item.is_some() => Some((entry.tick, SlabKey {
+                idx: token.idx,
+                stamp: entry.stamp,
+            })),
+            _ => None,
+        }
+    }
+
+    fn cancel(&mut self, token: TimerToken) -> Option<T> {
+        match self.entries.get(token.idx) {
+            Some(entry) if entry.generation == token.generation && entry.item.is_some() => self.release(token.idx),
+            _ => None,
+        }
+    }
+
+    // How many copies were still pending
+    fn cancel_group(&mut self, token: GroupToken) -> usize {
+        let members = match self.groups.get_mut(token.idx) {
+            Some(group) if group.generation == token.generation => core::mem::replace(&mut group.members, Vec::new()),
+            _ => return 0,
+        };
+
+        // Every member still pending is cancelled here, so the last of them releases the group
+        let mut cancelled = 0;
+        for member in members.iter() {
+            if self.cancel(*member).is_some() {
+                cancelled += 1;
+            }
+        }
+        cancelled
+    }
+}
+
+pub struct SlabWheel<T, S: SlotLike<Item = (SlabKey, usize)>, const LEVEL: usize, const CUTOFF: usize> {
+    wheel: Wheel<SlabKey, S, LEVEL, CUTOFF>,
+    slab: Slab<T>,
+}
+
+impl<T, S: SlotLike<Item = (SlabKey, usize)>, const LEVEL: usize, const CUTOFF: usize> SlabWheel<T, S, LEVEL, CUTOFF> {
+    pub fn new(elapsed: usize) -> Self {
+        Self {
+            wheel: Wheel::new(elapsed),
+            slab: Slab {
+                entries: Vec::new(),
+                free: Vec::new(),
+                groups: Vec::new(),
+                free_groups: Vec::new(),
+            },
+        }
+    }
+
+    pub fn elapsed(&self) -> usize {
+        self.wheel.elapsed()
+    }
+
+    pub fn schedule(&mut self, tick: usize, i: T) -> Result<TimerToken, T> {
+        let idx = self.slab.alloc();
+
+        let entry = &mut self.slab.entries[idx];
+        let key = SlabKey {
+            idx,
+            stamp: entry.stamp,
+        };
+
+        if self.wheel.schedule(tick, key).is_err() {
+            self.slab.free.push(idx);
+            return Err(i);
+        }
+
+        entry.tick = tick;
+        entry.item = Some(i);
+        Ok(TimerToken {
+            idx,
+            generation: entry.generation,
+        })
+    }
+
+    // A copy of the item at every tick, cancelled together through the token, see fast_forward_grouped.
+    // All or nothing: if any tick is rejected, the copies already scheduled are taken back and the item is returned.
+    // The item is cloned for all ticks but the last, which gets the original.
+    pub fn schedule_group(&mut self, ticks: &[usize], item: T) -> Result<GroupToken, T> where T: Clone {
+        let (last, rest) = match ticks.split_last() {
+            None => return Err(item),
+            Some(split) => split,
+        };
+
+        let mut members = Vec::with_capacity(ticks.len());
+        for tick in rest.iter() {
+            match self.schedule(*tick, item.clone()) {
+                Ok(token) => members.push(token),
+                Err(_) => return Err(self.abandon_group(members, item)),
+            }
+        }
+        let item = match self.schedule(*last, item) {
+            Ok(token) => {
+                members.push(token);
+                None
+            }
+            Err(item) => Some(item),
+        };
+        if let Some(item) = item {
+            return Err(self.abandon_group(members, item));
+        }
+
+        let idx = match self.slab.free_groups.pop() {
+            Some(idx) => idx,
+            None => {
+                self.slab.groups.push(Group {
+                    generation: 0,
+                    members: Vec::new(),
+                    live: 0,
+                });
+                self.slab.groups.len() - 1
+            }
+        };
+
+        for member in members.iter() {
+            self.slab.entries[member.idx].group = Some(idx);
+        }
+
+        let group = &mut self.slab.groups[idx];
+        group.live = members.len();
+        group.members = members;
+        Ok(GroupToken {
+            idx,
+            generation: group.generation,
+        })
+    }
+
+    fn abandon_group(&mut self, members: Vec<TimerToken>, item: T) -> T {
+        for member in members {
+            self.cancel(member);
+        }
+        item
+    }
+
+    // Take the key of a pending timer out of the wheel, wherever it has cascaded to by now
+    fn unschedule(&mut self, token: TimerToken) {
+        if let Some((tick, key)) = self.slab.key(token) {
+            let removed = self.wheel.remove(tick, |k| *k == key);
+            debug_assert!(removed.is_some());
+        }
+    }
+
+    // Move a pending timer to another tick. The token stays valid.
+    pub fn reschedule(&mut self, token: TimerToken, tick: usize) -> Result<(), RescheduleError> {
+        let entry = match self.slab.entries.get_mut(token.idx) {
+            Some(entry) if entry.generation == token.generation && entry.item.is_some() => entry,
+            _ => return Err(RescheduleError::Gone),
+        };
+
+        // Out first, so a move within a full slot finds room for the new key
+        let old = SlabKey {
+            idx: token.idx,
+            stamp: entry.stamp,
+        };
+        let removed = self.wheel.remove(entry.tick, |key| *key == old);
+        debug_assert!(removed.is_some());
+
+        let stamp = entry.stamp.wrapping_add(1);
+        let key = SlabKey {
+            idx: token.idx,
+            stamp,
+        };
+
+        if self.wheel.schedule(tick, key).is_err() {
+            // Back where it was taken from, there's room for it again. Only a parked one is refused for being overdue,
+            //   it goes back to the parked ones.
+            if let Some(old) = removed {
+                if let Err(old) = self.wheel.schedule(entry.tick, old) {
+                    self.wheel.park(core::iter::once((old, entry.tick)));
+                }
+            }
+            return Err(RescheduleError::Rejected);
+        }
+
+        entry.stamp = stamp;
+        entry.tick = tick;
+        Ok(())
+    }
+
+    // The item of a pending timer, None if it already fired or was cancelled
+    pub fn get_mut(&mut self, token: TimerToken) -> Option<&mut T> {
+        match self.slab.entries.get_mut(token.idx) {
+            Some(entry) if entry.generation == token.generation => entry.item.as_mut(),
+            _ => None,
+        }
+    }
+
+    // Hands back the item no matter how far its key has cascaded, None if it already fired or was cancelled.
+    // The key is taken out of its slot the way reschedule does, and the entry is free for reuse right away.
+    pub fn cancel(&mut self, token: TimerToken) -> Option<T> {
+        self.unschedule(token);
+        self.slab.cancel(token)
+    }
+
+    // Cancel every copy of the group still pending, returns how many there were.
+    // 0 once they have all fired or been cancelled, the token is stale then.
+    pub fn cancel_group(&mut self, token: GroupToken) -> usize {
+        let members = match self.slab.groups.get(token.idx) {
+            Some(group) if group.generation == token.generation => group.members.len(),
+            _ => return 0,
+        };
+
+        for i in 0..members {
+            let member = self.slab.groups[token.idx].members[i];
+            self.unschedule(member);
+        }
+        self.slab.cancel_group(token)
+    }
+
+    pub fn fast_forward<F: FnMut(T, usize)>(&mut self, moment: usize, mut f: F) {
+        self.fast_forward_grouped(moment, |item, ts| {
+            f(item, ts);
+            false
+        });
+    }
+
+    // fast_forward, where f returning true for a copy of a group cancels the rest of it,
+    //   e.g. once the first of several retries went through. Copies due in the same advance count too.
+    // Ignored for timers that aren't part of a group.
+    pub fn fast_forward_grouped<F: FnMut(T, usize) -> bool>(&mut self, moment: usize, mut f: F) {
+        let slab = &mut self.slab;
+        // Keys of the copies cancelled along the way. The wheel is busy until the advance is done, they're taken out then.
+        let mut cancelled = Vec::new();
+
+        self.wheel.fast_forward(moment, |key, ts| {
+            // Cancelled or rescheduled, the entry has moved on already
+            let entry = &slab.entries[key.idx];
+            if entry.stamp != key.stamp {
+                return;
+            }
+
+            let group = entry.group.map(|g| GroupToken {
+                idx: g,
+                generation: slab.groups[g].generation,
+            });
+
+            if let Some(item) = slab.release(key.idx) {
+                if f(item, ts) {
+                    if let Some(group) = group {
+                        if slab.groups[group.idx].generation == group.generation {
+                            cancelled.extend(slab.groups[group.idx].members.iter().filter_map(|member| slab.key(*member)));
+                        }
+                        slab.cancel_group(group);
+                    }
+                }
+            }
+        });
+
+        // Those due in this advance are gone already
+        for (tick, key) in cancelled {
+            self.wheel.remove(tick, |k| *k == key);
+        }
+    }
+
+    pub fn min_next_event(&self) -> Option<usize> {
+        self.wheel.min_next_event()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GroupToken, RescheduleError, SlabKey};
+    use crate::timing_wheel::hierarchical::BoundedSlot;
+    use std::collections::VecDeque;
+
+    type Wheel = super::SlabWheel<usize, VecDeque<(SlabKey, usize)>, 8, 6>;
+
+    #[test]
+    fn cancel() {
+        let mut wheel = super::SlabWheel::<String, VecDeque<(SlabKey, usize)>, 8, 6>::new(0);
+        let token = wheel.schedule(5000, "cancelled".to_string()).unwrap();
+        let kept = wheel.schedule(4200, "kept".to_string()).unwrap();
+
+        // Both have cascaded from level 2 down into level 1 by now
+        let mut fired = Vec::new();
+        wheel.fast_forward(4097, |item, at| fired.push((item, at)));
+        assert!(fired.is_empty());
+
+        assert_eq!(wheel.cancel(token), Some("cancelled".to_string()));
+        assert_eq!(wheel.cancel(token), None);
+        assert_eq!(wheel.wheel.pending_count(), 1);
+
+        // Takes over the cancelled entry, its leftover key must not fire this one early
+        let reused = wheel.schedule(6000, "reused".to_string()).unwrap();
+        assert_ne!(reused, token);
+
+        wheel.fast_forward(5500, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![("kept".to_string(), 4200)]);
+        assert_eq!(wheel.cancel(kept), None);
+
+        wheel.fast_forward(7000, |item, at| fired.push((item, at)));
+        assert_eq!(fired[1..], [("reused".to_string(), 6000)]);
+        assert_eq!(wheel.cancel(reused), None);
+        assert_eq!(wheel.min_next_event(), None);
+    }
+
+    #[test]
+    fn schedule_group() {
+        let mut wheel = Wheel::new(0);
+        let group = wheel.schedule_group(&[10, 20, 5000], 1).unwrap();
+        let single = wheel.schedule(30, 2).unwrap();
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(10, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(1, 10)]);
+
+        // The copy at 5000 has cascaded down to level 1 by now
+        wheel.fast_forward(4200, |item, at| fired.push((item, at)));
+        assert_eq!(fired[1..], [(1, 20), (2, 30)]);
+        assert_eq!(wheel.cancel_group(group), 1);
+        assert_eq!(wheel.cancel_group(group), 0);
+        assert_eq!(wheel.cancel(single), None);
+
+        wheel.fast_forward(6000, |_, _| panic!());
+
+        // All or nothing
+        assert_eq!(wheel.schedule_group(&[7000, 1 << 48], 3), Err(3));
+        assert_eq!(wheel.schedule_group(&[], 3), Err(3));
+        wheel.fast_forward(8000, |_, _| panic!());
+    }
+
+    #[test]
+    fn fast_forward_grouped() {
+        let mut wheel = Wheel::new(0);
+        let retries = wheel.schedule_group(&[10, 20, 30], 1).unwrap();
+        let other = wheel.schedule_group(&[20, 40], 2).unwrap();
+        assert_ne!(retries, other);
+
+        // The first retry of 1 failed, the second one went through. Its copy at 30 goes with it,
+        //   while 2 keeps both of its copies.
+        let mut fired = Vec::new();
+        wheel.fast_forward_grouped(100, |item, at| {
+            fired.push((item, at));
+            item == 1 && at == 20
+        });
+        assert_eq!(fired, vec![(1, 10), (1, 20), (2, 20), (2, 40)]);
+
+        // Both groups are gone, and their slots are reused without mixing up tokens
+        assert_eq!(wheel.cancel_group(retries), 0);
+        let reused: GroupToken = wheel.schedule_group(&[200], 3).unwrap();
+        assert_ne!(reused, retries);
+        assert_ne!(reused, other);
+
+        // Siblings due in the same advance are cancelled too
+        let burst = wheel.schedule_group(&[150, 150, 160], 4).unwrap();
+        wheel.fast_forward_grouped(300, |item, at| {
+            fired.push((item, at));
+            true
+        });
+        assert_eq!(fired[4..], [(4, 150), (3, 200)]);
+        assert_eq!(wheel.cancel_group(burst), 0);
+
+        // Copies beyond the advance are taken out of the wheel once it's done
+        wheel.schedule_group(&[310, 5000], 5).unwrap();
+        wheel.fast_forward_grouped(400, |item, at| {
+            fired.push((item, at));
+            true
+        });
+        assert_eq!(fired[6..], [(5, 310)]);
+        assert_eq!(wheel.wheel.pending_count(), 0);
+        assert_eq!(wheel.min_next_event(), None);
+    }
+
+    #[test]
+    fn reschedule() {
+        let mut wheel = Wheel::new(0);
+        let token = wheel.schedule(100, 1).unwrap();
+        let other = wheel.schedule(300, 2).unwrap();
+
+        wheel.reschedule(token, 500).unwrap();
+        assert_eq!(wheel.reschedule(token, 1 << 48), Err(RescheduleError::Rejected));
+
+        wheel.fast_forward(200, |_, _| panic!());
+        assert_eq!(wheel.reschedule(token, 150), Err(RescheduleError::Rejected));
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(400, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(2, 300)]);
+        assert_eq!(wheel.reschedule(other, 450), Err(RescheduleError::Gone));
+
+        // Pushed out twice more, then back to an earlier tick
+        wheel.reschedule(token, 600).unwrap();
+        wheel.reschedule(token, 700).unwrap();
+        wheel.reschedule(token, 500).unwrap();
+
+        wheel.fast_forward(1000, |item, at| fired.push((item, at)));
+        assert_eq!(fired[1..], [(1, 500)]);
+        assert_eq!(wheel.reschedule(token, 2000), Err(RescheduleError::Gone));
+
+        // The entry is reused, old tokens stay dead
+        let reused = wheel.schedule(1100, 3).unwrap();
+        assert_ne!(reused, token);
+        assert_eq!(wheel.reschedule(token, 1200), Err(RescheduleError::Gone));
+        wheel.fast_forward(1100, |item, at| fired.push((item, at)));
+        assert_eq!(fired[2..], [(3, 1100)]);
+    }
+
+    #[test]
+    fn reschedule_removes_old_key() {
+        let mut wheel = super::SlabWheel::<usize, BoundedSlot<(SlabKey, usize), 2>, 8, 6>::new(0);
+        let token = wheel.schedule(10, 1).unwrap();
+        wheel.schedule(10, 2).unwrap();
+
+        // Back and forth many times over, the old keys don't pile up in the slots
+        for i in 0..100 {
+            wheel.reschedule(token, 20 + i % 2).unwrap();
+        }
+        wheel.reschedule(token, 10).unwrap();
+        assert_eq!(wheel.wheel.pending_count(), 2);
+
+        // The full slot at 10 had room for it, and nothing is left at 20 or 21
+        wheel.reschedule(token, 5000).unwrap();
+        assert_eq!(wheel.min_next_event(), Some(10));
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(10, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(2, 10)]);
+        // Cascaded down a level by now, it's still found there
+        assert_eq!(wheel.min_next_event(), Some(4096));
+        wheel.fast_forward(4100, |item, at| fired.push((item, at)));
+        wheel.reschedule(token, 4200).unwrap();
+        assert_eq!(wheel.min_next_event(), Some(4160));
+        wheel.fast_forward(6000, |item, at| fired.push((item, at)));
+        assert_eq!(fired[1..], [(1, 4200)]);
+        assert_eq!(wheel.min_next_event(), None);
+    }
+
+    #[test]
+    fn reschedule_within_full_slot() {
+        // Level 1 slot 1 covers 64..128, and both of its places are taken
+        let mut wheel = super::SlabWheel::<usize, BoundedSlot<(SlabKey, usize), 2>, 8, 6>::new(0);
+        let token = wheel.schedule(70, 1).unwrap();
+        wheel.schedule(80, 2).unwrap();
+
+        wheel.reschedule(token, 90).unwrap();
+        assert_eq!(wheel.wheel.pending_count(), 2);
+
+        // Rejected, it stays at 90
+        wheel.schedule(91, 3).unwrap_err();
+        assert_eq!(wheel.reschedule(token, 1 << 48), Err(RescheduleError::Rejected));
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(100, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(2, 80), (1, 90)]);
+    }
+}
@@ -0,0 +1,293 @@
+use super::hierarchical::{SlotLike, Wheel};
+
+use alloc::vec::Vec;
+
+// Sticky entries are level-triggered: once the clock passes their tick, they fire on every
+//   advance until the caller acknowledges them with release(), which re-arms them at tick + period.
+// A periodic timer would re-arm by itself right after firing; a sticky one keeps firing until handled.
+// Armed entries sit in the wheel like any other item, so they fire in tick order along with the regular ones.
+// Once fired they move to the due list. Everything on it is overdue, so it fires first on every advance,
+//   which is still tick order, until release puts the entry back in the wheel.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StickyHandle {
+    idx: usize,
+    generation: usize,
+}
+
+// What the wheel actually stores
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StickyItem<T> {
+    Regular(T),
+    Sticky(StickyHandle),
+}
+
+struct Sticky<T> {
+    item: T,
+    tick: usize,
+    period: usize,
+    // Fired since it was last armed, so release may re-arm it
+    fired: bool,
+}
+
+struct Entry<T> {
+    generation: usize,
+    sticky: Option<Sticky<T>>,
+}
+
+fn get_mut<T>(entries: &mut [Entry<T>], handle: StickyHandle) -> Option<&mut Sticky<T>> {
+    let entry = entries.get_mut(handle.idx)?;
+    if entry.generation != handle.generation {
+        return None;
+    }
+
+    entry.sticky.as_mut()
+}
+
+pub struct StickyWheel<T, S: SlotLike<Item = (StickyItem<T>, usize)>, const LEVEL: usize, const CUTOFF: usize> {
+    wheel: Wheel<StickyItem<T>, S, LEVEL, CUTOFF>,
+    entries: Vec<Entry<T>>,
+    // Entries that are no longer in the wheel, in the order they fired
+    due: Vec<StickyHandle>,
+}
+
+impl<T: Clone, S: SlotLike<Item = (StickyItem<T>, usize)>, const LEVEL: usize, const CUTOFF: usize> StickyWheel<T, S, LEVEL, CUTOFF> {
+    pub fn new(elapsed: usize) -> Self {
+        Self {
+            wheel: Wheel::new(elapsed),
+            entries: Vec::new(),
+            due: Vec::new(),
+        }
+    }
+
+    pub fn wheel(&self) -> &Wheel<StickyItem<T>, S, LEVEL, CUTOFF> {
+        &self.wheel
+    }
+
+    pub fn elapsed(&self) -> usize {
+        self.wheel.elapsed()
+    }
+
+    pub fn schedule(&mut self, tick: usize, i: T) -> Result<(), T> {
+        self.wheel.schedule(tick, StickyItem::Regular(i)).map_err(|item| match item {
+            StickyItem::Regular(i) => i,
+            StickyItem::Sticky(_) => unreachable!(),
+        })
+    }
+
+    // Ticks past the wheel's horizon go to its overflow. Err if the tick is in the past or its slot is full.
+    pub fn schedule_sticky(&mut self, tick: usize, period: usize, i: T) -> Result<StickyHandle, T> {
+        if tick < self.wheel.elapsed() {
+            return Err(i);
+        }
+
+        // Reuse a released entry if there is one
+        let idx = match self.entries.iter().position(|e| e.sticky.is_none()) {
+            Some(idx) => idx,
+            None => {
+                self.entries.push(Entry {
+                    generation: 0,
+                    sticky: None,
+                });
+                self.entries.len() - 1
+            }
+        };
+
+        let handle = StickyHandle {
+            idx,
+            generation: self.entries[idx].generation,
+        };
+
+        // The current tick has been fast-forwarded past already, so it's due on the next advance
+        if tick == self.wheel.elapsed() {
+            self.due.push(handle);
+        } else if self.wheel.schedule_far(tick, StickyItem::Sticky(handle)).is_err() {
+            return Err(i);
+        }
+
+        self.entries[idx].sticky = Some(Sticky {
+            item: i,
+            tick,
+            period,
+            fired: false,
+        });
+
+        Ok(handle)
+    }
+
+    // Acknowledge a fired entry, re-arming it at tick + period, or at usize::MAX if that's past it.
+    // Returns false if the entry hasn't fired yet, the handle is stale, or the wheel can't take the new tick,
+    //   in which case it stays due and keeps firing.
+    pub fn release(&mut self, handle: StickyHandle) -> bool {
+        let elapsed = self.wheel.elapsed();
+        let tick = match get_mut(&mut self.entries, handle) {
+            Some(sticky) if sticky.fired => sticky.tick.saturating_add(sticky.period),
+            _ => return false,
+        };
+
+        // If it's been passed again already, it just stays due
+        if tick > elapsed {
+            if self.wheel.schedule_far(tick, StickyItem::Sticky(handle)).is_err() {
+                return false;
+            }
+
+            let pos = self.due.iter().position(|h| *h == handle).unwrap();
+            self.due.remove(pos);
+        }
+
+        let sticky = get_mut(&mut self.entries, handle).unwrap();
+        sticky.tick = tick;
+        sticky.fired = false;
+        true
+    }
+
+    // Remove the entry for good. The handle is invalidated afterwards.
+    pub fn unpin(&mut self, handle: StickyHandle) -> Option<T> {
+        let tick = get_mut(&mut self.entries, handle)?.tick;
+
+        match self.due.iter().position(|h| *h == handle) {
+            Some(pos) => {
+                self.due.remove(pos);
+            }
+            // A copy that can't be found anymore is skipped when it fires, as its handle is stale by then
+            None => {
+                self.wheel.remove(tick, |item| match item {
+                    StickyItem::Sticky(h) => *h == handle,
+                    StickyItem::Regular(_) => false,
+                });
+            }
+        }
+
+        let entry = &mut self.entries[handle.idx];
+        entry.generation = entry.generation.wrapping_add(1);
+        entry.sticky.take().map(|sticky| sticky.item)
+    }
+
+    // Fires everything due by moment in tick order, sticky entries with a clone of their item and the tick they're armed at.
+    // Fired but unreleased entries fire first, their tick has been passed before this advance.
+    pub fn fast_forward<F: FnMut(T, usize)>(&mut self, moment: usize, mut f: F) {
+        let entries = &mut self.entries;
+        let due = &mut self.due;
+
+        if moment > self.wheel.elapsed() {
+            for handle in due.iter() {
+                let sticky = get_mut(entries, *handle).unwrap();
+                sticky.fired = true;
+                f(sticky.item.clone(), sticky.tick);
+            }
+        }
+
+        self.wheel.fast_forward(moment, |item, at| match item {
+            StickyItem::Regular(i) => f(i, at),
+            StickyItem::Sticky(handle) => {
+                if let Some(sticky) = get_mut(entries, handle) {
+                    // Before f, so an entry stays due even if f panics
+                    sticky.fired = true;
+                    due.push(handle);
+                    f(sticky.item.clone(), sticky.tick);
+                }
+            }
+        });
+    }
+
+    // Due entries fire again on any advance, so they count as due at elapsed + 1
+    pub fn min_next_event(&self) -> Option<usize> {
+        let due = if self.due.is_empty() {
+            None
+        } else {
+            Some(self.wheel.elapsed() + 1)
+        };
+
+        match (self.wheel.min_next_event(), due) {
+            (Some(a), Some(b)) => Some(core::cmp::min(a, b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StickyItem;
+    use std::collections::VecDeque;
+
+    type Wheel = super::StickyWheel<usize, VecDeque<(StickyItem<usize>, usize)>, 8, 6>;
+
+    #[test]
+    fn sticky() {
+        let mut wheel = Wheel::new(0);
+        let handle = wheel.schedule_sticky(10, 5, 1).unwrap();
+        wheel.schedule(12, 2).unwrap();
+        assert_eq!(wheel.min_next_event(), Some(10));
+
+        // Not fired yet, nothing to release
+        assert!(!wheel.release(handle));
+
+        wheel.fast_forward(5, |_, _| panic!());
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(10, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(1, 10)]);
+        assert_eq!(wheel.min_next_event(), Some(11));
+
+        // Still pinned, fires again ahead of the later regular entry
+        fired.clear();
+        wheel.fast_forward(12, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(1, 10), (2, 12)]);
+
+        assert!(wheel.release(handle));
+        assert_eq!(wheel.min_next_event(), Some(15));
+        wheel.fast_forward(14, |_, _| panic!());
+
+        fired.clear();
+        wheel.fast_forward(15, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(1, 15)]);
+
+        assert_eq!(wheel.unpin(handle), Some(1));
+        assert_eq!(wheel.unpin(handle), None);
+        assert!(!wheel.release(handle));
+        assert_eq!(wheel.min_next_event(), None);
+        wheel.fast_forward(30, |_, _| panic!());
+
+        // The freed entry is reused, the old handle stays dead
+        let other = wheel.schedule_sticky(40, 1, 3).unwrap();
+        assert_ne!(other, handle);
+        assert_eq!(wheel.unpin(handle), None);
+        assert_eq!(wheel.unpin(other), Some(3));
+        wheel.fast_forward(50, |_, _| panic!());
+    }
+
+    #[test]
+    fn tick_order() {
+        let mut wheel = Wheel::new(0);
+        let late = wheel.schedule_sticky(20, 100, 1).unwrap();
+        wheel.schedule(5, 2).unwrap();
+        wheel.schedule_sticky(8, 100, 3).unwrap();
+        wheel.schedule(30, 4).unwrap();
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(30, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(2, 5), (3, 8), (1, 20), (4, 30)]);
+
+        // Re-armed within the next advance, so it fires at its tick, after the one still due
+        assert!(wheel.release(late));
+        wheel.schedule(130, 5).unwrap();
+        fired.clear();
+        wheel.fast_forward(130, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(3, 8), (1, 120), (5, 130)]);
+    }
+
+    #[test]
+    fn release_saturates() {
+        let mut wheel = Wheel::new(0);
+        let handle = wheel.schedule_sticky(10, usize::MAX, 1).unwrap();
+
+        let mut fired = Vec::new();
+        wheel.fast_forward(10, |item, at| fired.push((item, at)));
+        assert_eq!(fired, vec![(1, 10)]);
+
+        // Re-armed at usize::MAX instead of wrapping around into the past
+        assert!(wheel.release(handle));
+        wheel.fast_forward(1 << 40, |_, _| panic!());
+        assert_eq!(wheel.unpin(handle), Some(1));
+    }
+}
@@ -0,0 +1,30 @@
+use core::mem::MaybeUninit;
+
+// Build an array element by element. Elements are written into MaybeUninit storage first,
+//   and only read out as [T; N] once every one of them is initialized.
+// If f panics halfway through, the elements already built are leaked, not dropped.
+pub fn init_array<T, F: FnMut() -> T, const N: usize>(mut f: F) -> [T; N] {
+    let mut storage: [MaybeUninit<T>; N] = MaybeUninit::uninit_array();
+    for elem in storage.iter_mut() {
+        *elem = MaybeUninit::new(f());
+    }
+
+    // [MaybeUninit<T>; N] has the same layout as [T; N], and MaybeUninit never drops, so this moves them out exactly once
+    unsafe { (&storage as *const [MaybeUninit<T>; N] as *const [T; N]).read() }
+}
+
+#[cfg(test)]
+mod test {
+    use super::init_array;
+
+    #[test]
+    fn init_array_in_order() {
+        let mut next = 0;
+        let arr: [Box<usize>; 5] = init_array(|| {
+            next += 1;
+            Box::new(next)
+        });
+
+        assert_eq!(arr.iter().map(|b| **b).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+    }
+}